@@ -0,0 +1,269 @@
+use crate::ffmpeg::format::context::Input;
+use crate::ffmpeg::format::stream::Disposition;
+use crate::ffmpeg::media::Type;
+use crate::ffmpeg::util::rational::Rational;
+use std::collections::HashMap;
+use std::io;
+
+use crate::util;
+
+pub const DEFAULT_WINDOW: f64 = 1f64;
+// Shorter windows are mostly shorter than a packet anyway.
+pub const MIN_WINDOW: f64 = 0.01;
+// Windows are widened beyond this count, lest a bogus timestamp jump in a
+// damaged file allocate gigabytes.
+const MAX_WINDOW_COUNT: usize = 1_000_000;
+
+const PERCENTILES: [u32; 4] = [50, 90, 95, 99];
+
+#[derive(Clone, Copy, Debug)]
+pub struct PacketInfo {
+    // Decoding timestamp if available, presentation timestamp otherwise.
+    pub timestamp: i64,
+    pub duration: i64,
+    pub size: usize,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct BitRateSample {
+    // Seconds since the first packet of the stream.
+    pub start: f64,
+    pub end: f64,
+    pub bit_rate: u64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct BitRatePercentile {
+    pub percentile: u32,
+    #[serde(skip_serializing)]
+    pub _bit_rate: u64,
+    pub bit_rate: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct BitRateProfile {
+    pub index: usize,
+
+    // Window length in seconds, widened from the requested one if needed.
+    #[serde(skip_serializing)]
+    pub _window: f64,
+    pub window: String,
+
+    #[serde(skip_serializing)]
+    pub _peak: Option<u64>,
+    pub peak: Option<String>,
+    #[serde(skip_serializing)]
+    pub _peak_time: Option<f64>,
+    pub peak_time: Option<String>,
+    #[serde(skip_serializing)]
+    pub _minimum: Option<u64>,
+    pub minimum: Option<String>,
+    pub percentiles: Vec<BitRatePercentile>,
+
+    pub series: Vec<BitRateSample>,
+}
+
+fn format_bit_rate(rate: u64) -> String {
+    format!("{:.0} kb/s", rate as f64 / 1000f64)
+}
+
+// E.g., 1s, 0.5s or 3.6s.
+fn format_window(window: f64) -> String {
+    let s = format!("{:.3}", window);
+    format!("{}s", s.trim_end_matches('0').trim_end_matches('.'))
+}
+
+impl BitRateProfile {
+    // Packets are expected in decoding order. Each window's bit rate is
+    // the total size of packets starting within the window divided by the
+    // window length; the last window is usually partial, in which case
+    // only the portion covered by packets counts. The window is widened if
+    // the span would need more than MAX_WINDOW_COUNT windows.
+    pub fn from_packets(
+        index: usize,
+        time_base: Rational,
+        mut window: f64,
+        packets: &[PacketInfo],
+    ) -> BitRateProfile {
+        let time_base = f64::from(time_base);
+        let mut series = Vec::new();
+        if let Some(first) = packets.iter().map(|p| p.timestamp).min() {
+            let end = packets
+                .iter()
+                .map(|p| p.timestamp + p.duration.max(0))
+                .max()
+                .unwrap_or(first);
+            let span = (end - first) as f64 * time_base;
+            if span / window > MAX_WINDOW_COUNT as f64 {
+                let widened = span / MAX_WINDOW_COUNT as f64;
+                debug!(
+                    "stream #{}: {:.3}s span, widening bit rate windows from {}s to {}s",
+                    index, span, window, widened
+                );
+                window = widened;
+            }
+            let window_count = ((span / window).ceil() as usize).clamp(1, MAX_WINDOW_COUNT);
+            let mut bits = vec![0u64; window_count];
+            for p in packets {
+                let offset = (p.timestamp - first) as f64 * time_base;
+                let i = ((offset / window) as usize).min(window_count - 1);
+                bits[i] += p.size as u64 * 8;
+            }
+            for (i, &b) in bits.iter().enumerate() {
+                let start = i as f64 * window;
+                let end = ((i + 1) as f64 * window).min(span);
+                let length = if end > start { end - start } else { window };
+                series.push(BitRateSample {
+                    start,
+                    end: start + length,
+                    bit_rate: (b as f64 / length).round() as u64,
+                });
+            }
+        }
+
+        let peak_sample = series.iter().max_by_key(|s| s.bit_rate);
+        let _peak = peak_sample.map(|s| s.bit_rate);
+        let _peak_time = peak_sample.map(|s| s.start);
+        let _minimum = series.iter().map(|s| s.bit_rate).min();
+
+        let mut sorted = series.iter().map(|s| s.bit_rate).collect::<Vec<_>>();
+        sorted.sort_unstable();
+        let percentiles = if sorted.is_empty() {
+            Vec::new()
+        } else {
+            PERCENTILES
+                .iter()
+                .map(|&percentile| {
+                    // Nearest-rank method.
+                    let rank = (percentile as f64 / 100f64 * sorted.len() as f64).ceil() as usize;
+                    let _bit_rate = sorted[rank.max(1) - 1];
+                    BitRatePercentile {
+                        percentile,
+                        _bit_rate,
+                        bit_rate: format_bit_rate(_bit_rate),
+                    }
+                })
+                .collect()
+        };
+
+        BitRateProfile {
+            index,
+            _window: window,
+            window: format_window(window),
+            _peak,
+            peak: _peak.map(format_bit_rate),
+            _peak_time,
+            peak_time: _peak_time.map(util::format_seconds),
+            _minimum,
+            minimum: _minimum.map(format_bit_rate),
+            percentiles,
+            series,
+        }
+    }
+
+    pub fn series_csv_header() -> String {
+        "path,stream,start,end,bit_rate".to_string()
+    }
+
+    pub fn series_csv_rows(&self, path: &str) -> Vec<String> {
+        self.series
+            .iter()
+            .map(|s| {
+                format!(
+                    "\"{}\",{},{:.3},{:.3},{}",
+                    path.replace('"', "\"\""),
+                    self.index,
+                    s.start,
+                    s.end,
+                    s.bit_rate
+                )
+            })
+            .collect()
+    }
+}
+
+// Walks all packets of the input and computes the bit rate profile of each
+// audio and video stream over windows of the given length in seconds.
+// Attached pictures (cover art) are skipped.
+pub fn analyze_bit_rates(input: &mut Input, window: f64) -> io::Result<Vec<BitRateProfile>> {
+    let mut time_bases = HashMap::new();
+    let mut packets: HashMap<usize, Vec<PacketInfo>> = HashMap::new();
+    for stream in input.streams() {
+        let medium = stream.parameters().medium();
+        if (medium == Type::Video || medium == Type::Audio)
+            && !stream.disposition().contains(Disposition::ATTACHED_PIC)
+        {
+            time_bases.insert(stream.index(), stream.time_base());
+            packets.insert(stream.index(), Vec::new());
+        }
+    }
+
+    for (stream, packet) in input.packets() {
+        if let Some(stream_packets) = packets.get_mut(&stream.index()) {
+            if let Some(timestamp) = packet.dts().or_else(|| packet.pts()) {
+                stream_packets.push(PacketInfo {
+                    timestamp,
+                    duration: packet.duration(),
+                    size: packet.size(),
+                });
+            }
+        }
+    }
+
+    let mut indices = packets.keys().cloned().collect::<Vec<_>>();
+    indices.sort();
+    Ok(indices
+        .into_iter()
+        .map(|i| BitRateProfile::from_packets(i, time_bases[&i], window, &packets[&i]))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bit_rate_profile_over_windows() {
+        // 25 packets per second for 2.5 seconds; every packet in the
+        // second second is twice as large.
+        let packets = (0..63)
+            .map(|i| PacketInfo {
+                timestamp: i * 40,
+                duration: 40,
+                size: if (25..50).contains(&i) { 1000 } else { 500 },
+            })
+            .collect::<Vec<_>>();
+        let profile = BitRateProfile::from_packets(0, Rational(1, 1000), 1f64, &packets);
+        assert_eq!(
+            vec![100000, 200000, 100000],
+            profile
+                .series
+                .iter()
+                .map(|s| s.bit_rate)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(Some(200000), profile._peak);
+        assert_eq!(Some(1f64), profile._peak_time);
+        assert_eq!(Some(100000), profile._minimum);
+        assert_eq!(100000, profile.percentiles[0]._bit_rate);
+        assert_eq!(200000, profile.percentiles[3]._bit_rate);
+        assert_eq!("1s", profile.window);
+        assert_eq!("0.25s", format_window(0.25));
+    }
+
+    #[test]
+    fn bit_rate_windows_are_capped() {
+        // A timestamp jump of about 12 days in milliseconds.
+        let packets = [0, 1_000_000_000]
+            .iter()
+            .map(|&timestamp| PacketInfo {
+                timestamp,
+                duration: 40,
+                size: 500,
+            })
+            .collect::<Vec<_>>();
+        let profile = BitRateProfile::from_packets(0, Rational(1, 1000), MIN_WINDOW, &packets);
+        assert_eq!(MAX_WINDOW_COUNT, profile.series.len());
+        assert!(profile._window > 1f64);
+    }
+}
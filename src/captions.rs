@@ -0,0 +1,42 @@
+// Detection of ATSC A/53 closed captions (CEA-608/708) carried as side data
+// in video frames, e.g., in H.264 SEI messages or MPEG-2 user data.
+
+use crate::ffmpeg::codec::packet::side_data::Type as PacketSideDataType;
+use crate::ffmpeg::format::context::Input;
+use crate::ffmpeg::format::stream::Disposition;
+use crate::ffmpeg::frame::side_data::Type as SideDataType;
+use std::io;
+
+use crate::decode;
+
+// Number of frames sampled, evenly spaced throughout the video. Captioned
+// streams carry caption data in practically every frame.
+const SAMPLE_COUNT: i64 = 10;
+
+// Checks the given video stream for A53 caption data: first the stream's
+// side data, as set by some demuxers, then frames sampled from the stream.
+// Returns None for attached pictures (cover art), which never carry
+// captions.
+pub fn detect_closed_captions(input: &mut Input, index: usize) -> io::Result<Option<bool>> {
+    match input.stream(index) {
+        Some(stream) => {
+            if stream.disposition().contains(Disposition::ATTACHED_PIC) {
+                return Ok(None);
+            }
+            if stream
+                .side_data()
+                .any(|d| d.kind() == PacketSideDataType::A53CC)
+            {
+                return Ok(Some(true));
+            }
+        }
+        None => return Ok(None),
+    }
+
+    let timestamps = decode::sample_timestamps(input, SAMPLE_COUNT);
+    let mut found = false;
+    decode::sample_video_frames(input, index, &timestamps, |frame| {
+        found = found || frame.side_data(SideDataType::A53CC).is_some();
+    })?;
+    Ok(Some(found))
+}
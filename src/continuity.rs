@@ -0,0 +1,647 @@
+// Timestamp continuity analysis: backwards timestamps, gaps and
+// discontinuities in audio and video streams, plus, for MPEG transport
+// streams, PCR jumps and continuity counter errors read from the raw TS
+// packets.
+
+use crate::ffmpeg::format::context::Input;
+use crate::ffmpeg::format::stream::Disposition;
+use crate::ffmpeg::media::Type;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+
+use crate::util;
+
+// Forward jumps beyond the expected timestamp by more than this many
+// seconds are gaps...
+const GAP_THRESHOLD: f64 = 0.5;
+// ...and jumps (in either direction) by at least this many seconds are
+// discontinuities, e.g., timestamp resets; the same as libavformat's
+// default dts_delta_threshold.
+const DISCONTINUITY_THRESHOLD: f64 = 10f64;
+// ISO/IEC 13818-1 requires PCRs at most 100ms apart.
+const PCR_JUMP_THRESHOLD: f64 = 0.1;
+// Only the first few events of each stream are kept; all are counted.
+const MAX_EVENTS: usize = 10;
+
+const TS_PACKET_SIZE: usize = 188;
+const TS_SYNC_BYTE: u8 = 0x47;
+const NULL_PID: u16 = 0x1fff;
+const PCR_CLOCK: f64 = 27_000_000f64;
+// PCRs wrap around with their 33-bit base, about every 26.5 hours.
+const PCR_WRAP: u64 = (1 << 33) * 300;
+// After losing sync, a sync byte only counts if the next this many packets
+// (if not past the end) start with one, too.
+const SYNC_CONFIRMATIONS: usize = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Backwards,
+    Gap,
+    Discontinuity,
+    PcrJump,
+    ContinuityError,
+    SyncLoss,
+}
+
+impl EventKind {
+    fn noun(self, plural: bool) -> &'static str {
+        let (singular, plural_form) = match self {
+            EventKind::Backwards => ("backwards timestamp", "backwards timestamps"),
+            EventKind::Gap => ("gap", "gaps"),
+            EventKind::Discontinuity => ("discontinuity", "discontinuities"),
+            EventKind::PcrJump => ("PCR jump", "PCR jumps"),
+            EventKind::ContinuityError => ("continuity error", "continuity errors"),
+            EventKind::SyncLoss => ("sync loss", "sync losses"),
+        };
+        if plural {
+            plural_form
+        } else {
+            singular
+        }
+    }
+
+    fn describe(self, count: u64) -> String {
+        format!("{} {}", count, self.noun(count != 1))
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ContinuityEvent {
+    pub kind: EventKind,
+    pub description: String,
+    // Seconds since the start of the file.
+    #[serde(skip_serializing)]
+    pub _time: f64,
+    pub time: String,
+    // Difference from the expected timestamp, in seconds; not applicable to
+    // continuity counter errors.
+    #[serde(skip_serializing)]
+    pub _delta: Option<f64>,
+    pub delta: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ContinuityReport {
+    // None for PIDs not associated with any stream, e.g., a dedicated PCR
+    // PID, and for the transport stream as a whole (sync losses).
+    pub index: Option<usize>,
+    #[serde(skip_serializing)]
+    pub _pid: Option<u16>,
+    pub pid: Option<String>,
+    pub location: String,
+    pub backwards: u64,
+    pub gaps: u64,
+    pub discontinuities: u64,
+    pub pcr_jumps: u64,
+    pub continuity_errors: u64,
+    pub sync_losses: u64,
+    // Counts of the above, e.g., "2 gaps, 1 PCR jump".
+    pub summary: String,
+    pub events: Vec<ContinuityEvent>,
+}
+
+impl ContinuityReport {
+    fn new(index: Option<usize>, _pid: Option<u16>) -> ContinuityReport {
+        let pid = _pid.map(|p| format!("0x{:x}", p));
+        ContinuityReport {
+            index,
+            _pid,
+            location: describe_location(index, &pid),
+            pid,
+            backwards: 0,
+            gaps: 0,
+            discontinuities: 0,
+            pcr_jumps: 0,
+            continuity_errors: 0,
+            sync_losses: 0,
+            summary: String::new(),
+            events: Vec::new(),
+        }
+    }
+
+    fn add(&mut self, kind: EventKind, time: f64, delta: Option<f64>) {
+        self.add_described(kind, kind.noun(false).to_string(), time, delta);
+    }
+
+    fn add_described(
+        &mut self,
+        kind: EventKind,
+        description: String,
+        time: f64,
+        delta: Option<f64>,
+    ) {
+        match kind {
+            EventKind::Backwards => self.backwards += 1,
+            EventKind::Gap => self.gaps += 1,
+            EventKind::Discontinuity => self.discontinuities += 1,
+            EventKind::PcrJump => self.pcr_jumps += 1,
+            EventKind::ContinuityError => self.continuity_errors += 1,
+            EventKind::SyncLoss => self.sync_losses += 1,
+        }
+        self.summarize();
+        if self.events.len() < MAX_EVENTS {
+            self.events.push(ContinuityEvent {
+                kind,
+                description,
+                _time: time,
+                time: util::format_seconds(time.max(0f64)),
+                _delta: delta,
+                delta: delta.map(|d| format!("{:+.3}s", d)),
+            });
+        }
+    }
+
+    fn summarize(&mut self) {
+        self.summary = [
+            (EventKind::Backwards, self.backwards),
+            (EventKind::Gap, self.gaps),
+            (EventKind::Discontinuity, self.discontinuities),
+            (EventKind::PcrJump, self.pcr_jumps),
+            (EventKind::ContinuityError, self.continuity_errors),
+            (EventKind::SyncLoss, self.sync_losses),
+        ]
+        .iter()
+        .filter(|&&(_, count)| count > 0)
+        .map(|&(kind, count)| kind.describe(count))
+        .collect::<Vec<_>>()
+        .join(", ");
+    }
+
+    fn is_clean(&self) -> bool {
+        self.backwards
+            + self.gaps
+            + self.discontinuities
+            + self.pcr_jumps
+            + self.continuity_errors
+            + self.sync_losses
+            == 0
+    }
+
+    fn merge(&mut self, other: ContinuityReport) {
+        self.backwards += other.backwards;
+        self.gaps += other.gaps;
+        self.discontinuities += other.discontinuities;
+        self.pcr_jumps += other.pcr_jumps;
+        self.continuity_errors += other.continuity_errors;
+        self.sync_losses += other.sync_losses;
+        self.summarize();
+        self.events.extend(other.events);
+        self.events
+            .sort_by(|a, b| a._time.partial_cmp(&b._time).unwrap());
+        self.events.truncate(MAX_EVENTS);
+    }
+}
+
+fn describe_location(index: Option<usize>, pid: &Option<String>) -> String {
+    match (index, pid) {
+        (Some(i), Some(p)) => format!("#{} (PID {})", i, p),
+        (Some(i), None) => format!("#{}", i),
+        (None, Some(p)) => format!("PID {}", p),
+        (None, None) => "Transport stream".to_string(),
+    }
+}
+
+// The difference a - b between two PCRs in seconds, taking wraparound
+// into account.
+fn pcr_difference(a: u64, b: u64) -> f64 {
+    let difference = (a + PCR_WRAP - b % PCR_WRAP) % PCR_WRAP;
+    if difference > PCR_WRAP / 2 {
+        -((PCR_WRAP - difference) as f64) / PCR_CLOCK
+    } else {
+        difference as f64 / PCR_CLOCK
+    }
+}
+
+// Classifies the jump from the expected timestamp to the actual one.
+fn classify_jump(delta: f64) -> Option<EventKind> {
+    if delta.abs() >= DISCONTINUITY_THRESHOLD {
+        Some(EventKind::Discontinuity)
+    } else if delta < 0f64 {
+        Some(EventKind::Backwards)
+    } else if delta > GAP_THRESHOLD {
+        Some(EventKind::Gap)
+    } else {
+        None
+    }
+}
+
+struct TimestampTracker {
+    time_base: f64,
+    // Previous decoding timestamp, and the timestamp expected next.
+    previous: Option<(i64, i64)>,
+}
+
+impl TimestampTracker {
+    // Returns the jump from the expected timestamp in seconds, if it is
+    // noteworthy.
+    fn update(&mut self, timestamp: i64, duration: i64) -> Option<(EventKind, f64)> {
+        let event = self.previous.and_then(|(previous, expected)| {
+            if timestamp < previous {
+                let delta = (timestamp - previous) as f64 * self.time_base;
+                Some((classify_jump(delta).unwrap_or(EventKind::Backwards), delta))
+            } else {
+                let delta = (timestamp - expected) as f64 * self.time_base;
+                classify_jump(delta)
+                    .filter(|&k| k != EventKind::Backwards)
+                    .map(|k| (k, delta))
+            }
+        });
+        self.previous = Some((timestamp, timestamp + duration.max(0)));
+        event
+    }
+}
+
+// Continuity counter and PCR state of a PID in a transport stream.
+#[derive(Default)]
+struct PidState {
+    continuity_counter: Option<u8>,
+    duplicate_seen: bool,
+    last_pcr: Option<u64>,
+}
+
+struct TransportStreamScanner {
+    pids: HashMap<u16, PidState>,
+    reports: HashMap<u16, ContinuityReport>,
+    // Sync losses are not tied to any PID.
+    sync_report: ContinuityReport,
+    first_pcr: Option<u64>,
+    // Time of the latest PCR on any PID, used to place continuity errors.
+    now: f64,
+}
+
+impl TransportStreamScanner {
+    fn new() -> TransportStreamScanner {
+        TransportStreamScanner {
+            pids: HashMap::new(),
+            reports: HashMap::new(),
+            sync_report: ContinuityReport::new(None, None),
+            first_pcr: None,
+            now: 0f64,
+        }
+    }
+
+    fn report(&mut self, pid: u16) -> &mut ContinuityReport {
+        self.reports
+            .entry(pid)
+            .or_insert_with(|| ContinuityReport::new(None, Some(pid)))
+    }
+
+    fn add_packet(&mut self, packet: &[u8]) {
+        if packet.len() < TS_PACKET_SIZE || packet[0] != TS_SYNC_BYTE {
+            return;
+        }
+        let pid = (((packet[1] & 0x1f) as u16) << 8) | packet[2] as u16;
+        if pid == NULL_PID {
+            return;
+        }
+        let adaptation_field_control = (packet[3] >> 4) & 0x3;
+        let has_payload = adaptation_field_control & 0x1 != 0;
+        let continuity_counter = packet[3] & 0xf;
+
+        let mut discontinuity = false;
+        let mut pcr = None;
+        if adaptation_field_control & 0x2 != 0 && packet[4] > 0 {
+            let flags = packet[5];
+            discontinuity = flags & 0x80 != 0;
+            if flags & 0x10 != 0 && packet[4] >= 7 {
+                let b = &packet[6..12];
+                let base = ((b[0] as u64) << 25)
+                    | ((b[1] as u64) << 17)
+                    | ((b[2] as u64) << 9)
+                    | ((b[3] as u64) << 1)
+                    | ((b[4] as u64) >> 7);
+                let extension = (((b[4] & 0x1) as u64) << 8) | b[5] as u64;
+                pcr = Some(base * 300 + extension);
+            }
+        }
+
+        if let Some(pcr) = pcr {
+            let first_pcr = *self.first_pcr.get_or_insert(pcr);
+            self.now = pcr_difference(pcr, first_pcr);
+        }
+        let now = self.now;
+
+        let state = self.pids.entry(pid).or_default();
+        let mut continuity_error = false;
+        if has_payload {
+            if let Some(previous) = state.continuity_counter {
+                if discontinuity {
+                    state.duplicate_seen = false;
+                } else if continuity_counter == previous && !state.duplicate_seen {
+                    // A single duplicate packet is allowed.
+                    state.duplicate_seen = true;
+                } else if continuity_counter != (previous + 1) & 0xf {
+                    continuity_error = true;
+                } else {
+                    state.duplicate_seen = false;
+                }
+            }
+            state.continuity_counter = Some(continuity_counter);
+        }
+        let mut pcr_jump = None;
+        if let Some(pcr) = pcr {
+            if let (Some(last), false) = (state.last_pcr, discontinuity) {
+                let delta = pcr_difference(pcr, last);
+                if !(0f64..=PCR_JUMP_THRESHOLD).contains(&delta) {
+                    pcr_jump = Some(delta);
+                }
+            }
+            state.last_pcr = Some(pcr);
+        }
+
+        if continuity_error {
+            self.report(pid).add(EventKind::ContinuityError, now, None);
+        }
+        if let Some(delta) = pcr_jump {
+            self.report(pid).add(EventKind::PcrJump, now, Some(delta));
+        }
+    }
+
+    // offset is the position in the file of the packet that should have
+    // started with a sync byte.
+    fn lose_sync(&mut self, offset: u64) {
+        let description = format!("sync lost at byte {}", offset);
+        let now = self.now;
+        self.sync_report
+            .add_described(EventKind::SyncLoss, description, now, None);
+    }
+
+    fn finish(self) -> Vec<ContinuityReport> {
+        let mut reports = self.reports.into_values().collect::<Vec<_>>();
+        if !self.sync_report.is_clean() {
+            reports.push(self.sync_report);
+        }
+        reports
+    }
+}
+
+// Scans the raw packets of a transport stream file. M2TS (192-byte packets
+// with a 4-byte timecode prefix) and 204-byte packets with trailing
+// Reed-Solomon parity are supported, too.
+fn scan_transport_stream(path: &Path) -> io::Result<Vec<ContinuityReport>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut head = Vec::new();
+    (&mut reader).take(204 * 4).read_to_end(&mut head)?;
+    let layout = [(188, 0), (192, 4), (204, 0)]
+        .iter()
+        .cloned()
+        .find(|&(size, offset)| {
+            head.len() >= size * 3 + offset
+                && (0..3).all(|i| head[i * size + offset] == TS_SYNC_BYTE)
+        });
+    let (size, offset) = match layout {
+        Some(layout) => layout,
+        None => return Ok(Vec::new()),
+    };
+
+    scan_packets(io::Cursor::new(head).chain(reader), size, offset)
+}
+
+// Tops up the window to hold the given number of bytes, unless the end of
+// the input is reached.
+fn fill_window<R: Read>(reader: &mut R, window: &mut Vec<u8>, length: usize) -> io::Result<()> {
+    if window.len() < length {
+        reader
+            .take((length - window.len()) as u64)
+            .read_to_end(window)?;
+    }
+    Ok(())
+}
+
+// Feeds packets of the given size (sync byte at the given offset) to a
+// scanner. On a missing sync byte, sync is regained at the next sync byte
+// confirmed by the packets that follow.
+fn scan_packets<R: Read>(
+    mut reader: R,
+    size: usize,
+    offset: usize,
+) -> io::Result<Vec<ContinuityReport>> {
+    let window_size = size * (SYNC_CONFIRMATIONS + 1);
+    let synced = |window: &[u8]| {
+        (0..=SYNC_CONFIRMATIONS).all(|i| {
+            window
+                .get(i * size + offset)
+                .is_none_or(|&b| b == TS_SYNC_BYTE)
+        })
+    };
+
+    let mut scanner = TransportStreamScanner::new();
+    let mut window = Vec::with_capacity(window_size);
+    // Position in the file of the start of the window.
+    let mut position = 0u64;
+    loop {
+        fill_window(&mut reader, &mut window, window_size)?;
+        if window.len() < size {
+            break;
+        }
+        if window[offset] != TS_SYNC_BYTE {
+            scanner.lose_sync(position + offset as u64);
+            while window.len() >= size && !synced(&window) {
+                window.remove(0);
+                position += 1;
+                fill_window(&mut reader, &mut window, window_size)?;
+            }
+            if window.len() < size {
+                break;
+            }
+        }
+        scanner.add_packet(&window[offset..size]);
+        window.drain(..size);
+        position += size as u64;
+    }
+    Ok(scanner.finish())
+}
+
+// Walks all packets of the input and reports timestamp problems of each
+// audio and video stream, and, for transport streams, PCR jumps and
+// continuity counter errors of each PID. Only streams and PIDs with
+// problems are reported.
+pub fn analyze_continuity(input: &mut Input, path: &Path) -> io::Result<Vec<ContinuityReport>> {
+    let start = match unsafe { (*input.as_ptr()).start_time } {
+        crate::ffmpeg::ffi::AV_NOPTS_VALUE => 0f64,
+        t => t as f64 / crate::ffmpeg::ffi::AV_TIME_BASE as f64,
+    };
+    let is_transport_stream = input.format().name().split(',').any(|n| n == "mpegts");
+
+    let mut trackers: HashMap<usize, TimestampTracker> = HashMap::new();
+    let mut reports: HashMap<usize, ContinuityReport> = HashMap::new();
+    let mut pid_indices: HashMap<u16, usize> = HashMap::new();
+    for stream in input.streams() {
+        let medium = stream.parameters().medium();
+        if is_transport_stream && (0..NULL_PID as i32).contains(&stream.id()) {
+            pid_indices.insert(stream.id() as u16, stream.index());
+        }
+        if (medium == Type::Video || medium == Type::Audio)
+            && !stream.disposition().contains(Disposition::ATTACHED_PIC)
+        {
+            trackers.insert(
+                stream.index(),
+                TimestampTracker {
+                    time_base: f64::from(stream.time_base()),
+                    previous: None,
+                },
+            );
+            let pid = pid_indices
+                .get(&(stream.id() as u16))
+                .map(|_| stream.id() as u16);
+            reports.insert(
+                stream.index(),
+                ContinuityReport::new(Some(stream.index()), pid),
+            );
+        }
+    }
+
+    for (stream, packet) in input.packets() {
+        let index = stream.index();
+        if let Some(tracker) = trackers.get_mut(&index) {
+            if let Some(timestamp) = packet.dts().or_else(|| packet.pts()) {
+                if let Some((kind, delta)) = tracker.update(timestamp, packet.duration()) {
+                    let time = timestamp as f64 * tracker.time_base - start;
+                    reports
+                        .get_mut(&index)
+                        .unwrap()
+                        .add(kind, time, Some(delta));
+                }
+            }
+        }
+    }
+
+    let mut pid_reports = Vec::new();
+    if is_transport_stream {
+        for mut report in scan_transport_stream(path)? {
+            let index = report._pid.and_then(|pid| pid_indices.get(&pid).cloned());
+            match index.and_then(|i| reports.get_mut(&i)) {
+                Some(stream_report) => stream_report.merge(report),
+                None => {
+                    // Streams not checked for timestamp problems, e.g.,
+                    // subtitles, and sync losses.
+                    if index.is_some() {
+                        report.index = index;
+                        report.location = describe_location(index, &report.pid);
+                    }
+                    pid_reports.push(report);
+                }
+            }
+        }
+    }
+
+    let mut results = reports
+        .into_values()
+        .chain(pid_reports)
+        .filter(|r| !r.is_clean())
+        .collect::<Vec<_>>();
+    results.sort_by_key(|r| (r.index.is_none(), r.index, r._pid));
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ts_packet(pid: u16, continuity_counter: u8, pcr: Option<u64>) -> Vec<u8> {
+        let mut packet = vec![0xffu8; TS_PACKET_SIZE];
+        packet[0] = TS_SYNC_BYTE;
+        packet[1] = (pid >> 8) as u8;
+        packet[2] = pid as u8;
+        match pcr {
+            Some(pcr) => {
+                packet[3] = 0x30 | continuity_counter;
+                packet[4] = 7;
+                packet[5] = 0x10;
+                let (base, extension) = (pcr / 300, pcr % 300);
+                packet[6] = (base >> 25) as u8;
+                packet[7] = (base >> 17) as u8;
+                packet[8] = (base >> 9) as u8;
+                packet[9] = (base >> 1) as u8;
+                packet[10] = ((base & 0x1) << 7) as u8 | 0x7e | (extension >> 8) as u8;
+                packet[11] = extension as u8;
+            }
+            None => packet[3] = 0x10 | continuity_counter,
+        }
+        packet
+    }
+
+    #[test]
+    fn transport_stream_errors() {
+        let mut scanner = TransportStreamScanner::new();
+        // PCRs 40ms apart, except for a 2s jump at the fourth one; a
+        // duplicate packet (allowed) and a lost packet on the payload PID.
+        let step = (PCR_CLOCK * 0.04) as u64;
+        for (cc, pcr) in [(0, 0), (1, step), (2, step * 2), (3, step * 52)] {
+            scanner.add_packet(&ts_packet(0x100, cc, Some(pcr)));
+        }
+        for cc in [0, 1, 1, 2, 4, 5] {
+            scanner.add_packet(&ts_packet(0x101, cc, None));
+        }
+        let mut reports = scanner.finish();
+        reports.sort_by_key(|r| r._pid);
+        assert_eq!(2, reports.len());
+        assert_eq!(1, reports[0].pcr_jumps);
+        assert_eq!(0, reports[0].continuity_errors);
+        assert_eq!("+2.000s", reports[0].events[0].delta.as_ref().unwrap());
+        assert_eq!(0, reports[1].pcr_jumps);
+        assert_eq!(1, reports[1].continuity_errors);
+        assert_eq!("1 continuity error", reports[1].summary);
+        assert_eq!(2.08, reports[1].events[0]._time);
+    }
+
+    #[test]
+    fn transport_stream_pcr_wraparound() {
+        let mut scanner = TransportStreamScanner::new();
+        let step = (PCR_CLOCK * 0.04) as u64;
+        for (cc, pcr) in [
+            (0, PCR_WRAP - step * 2),
+            (1, PCR_WRAP - step),
+            (2, 0),
+            (3, step),
+        ] {
+            scanner.add_packet(&ts_packet(0x100, cc, Some(pcr)));
+        }
+        assert!(scanner.finish().is_empty());
+    }
+
+    #[test]
+    fn transport_stream_resync() {
+        // A stray byte after the third packet, and garbage containing a
+        // lone sync byte after the sixth.
+        let mut data = Vec::new();
+        for cc in 0..10 {
+            data.extend(ts_packet(0x101, cc, None));
+            match cc {
+                2 => data.push(0),
+                5 => data.extend([0, TS_SYNC_BYTE, 0, 0]),
+                _ => (),
+            }
+        }
+        let reports = scan_packets(io::Cursor::new(data), TS_PACKET_SIZE, 0).unwrap();
+        assert_eq!(1, reports.len());
+        let report = &reports[0];
+        assert_eq!(None, report._pid);
+        assert_eq!("Transport stream", report.location);
+        assert_eq!(2, report.sync_losses);
+        assert_eq!(0, report.continuity_errors);
+        assert_eq!("sync lost at byte 564", report.events[0].description);
+        assert_eq!("sync lost at byte 1129", report.events[1].description);
+    }
+
+    #[test]
+    fn timestamp_jumps() {
+        // Quarter-second packets.
+        let mut tracker = TimestampTracker {
+            time_base: 0.25,
+            previous: None,
+        };
+        assert_eq!(None, tracker.update(0, 1));
+        assert_eq!(None, tracker.update(1, 1));
+        assert_eq!(None, tracker.update(4, 1));
+        assert_eq!(Some((EventKind::Gap, 0.75)), tracker.update(8, 1));
+        assert_eq!(Some((EventKind::Backwards, -0.5)), tracker.update(6, 1));
+        assert_eq!(
+            Some((EventKind::Discontinuity, 24f64)),
+            tracker.update(103, 1)
+        );
+    }
+}
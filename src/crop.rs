@@ -0,0 +1,126 @@
+// Detection of letterboxing and pillarboxing, in the spirit of FFmpeg's
+// cropdetect filter: decoded frames sampled throughout the video are
+// scanned for rows and columns that are not black, and the active picture
+// area is the smallest rectangle containing all of them.
+
+use crate::ffmpeg::format::context::Input;
+use crate::ffmpeg::util::rational::Rational;
+use std::io;
+
+use crate::decode::{self, LumaPlane};
+
+// Number of frames sampled, evenly spaced throughout the video.
+const SAMPLE_COUNT: i64 = 10;
+
+// Rows and columns with a mean normalized luma not above this value are
+// considered black; roughly the same as cropdetect's default limit of 24.
+const BLACK_LIMIT: f64 = 0.1;
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct CropArea {
+    pub width: u32,
+    pub height: u32,
+    // Offset of the top left corner.
+    pub x: u32,
+    pub y: u32,
+    // X11-style geometry, e.g., 1920x800+0+140.
+    pub geometry: String,
+}
+
+impl CropArea {
+    fn new(left: usize, top: usize, right: usize, bottom: usize) -> CropArea {
+        let width = (right - left + 1) as u32;
+        let height = (bottom - top + 1) as u32;
+        let (x, y) = (left as u32, top as u32);
+        CropArea {
+            width,
+            height,
+            x,
+            y,
+            geometry: format!("{}x{}+{}+{}", width, height, x, y),
+        }
+    }
+
+    // The display aspect ratio of the active picture area, given the
+    // sample aspect ratio of the video.
+    pub fn aspect_ratio(&self, sar: Rational) -> Rational {
+        (sar * Rational(self.width as i32, self.height as i32)).reduce()
+    }
+}
+
+// Bounds (left, top, right, bottom), inclusive, of the non-black area of a
+// picture, or None if the entire picture is black.
+fn active_bounds(luma: &LumaPlane) -> Option<(usize, usize, usize, usize)> {
+    let row_active = |y: usize| {
+        (0..luma.width).map(|x| luma.value(x, y)).sum::<f64>() / luma.width as f64 > BLACK_LIMIT
+    };
+    let top = (0..luma.height).find(|&y| row_active(y))?;
+    let bottom = (0..luma.height).rev().find(|&y| row_active(y))?;
+    let column_active = |x: usize| {
+        (top..=bottom).map(|y| luma.value(x, y)).sum::<f64>() / (bottom - top + 1) as f64
+            > BLACK_LIMIT
+    };
+    let left = (0..luma.width).find(|&x| column_active(x))?;
+    let right = (0..luma.width).rev().find(|&x| column_active(x))?;
+    Some((left, top, right, bottom))
+}
+
+// Merges per-picture bounds into the smallest area containing them all.
+fn merge_bounds(bounds: &[(usize, usize, usize, usize)]) -> Option<CropArea> {
+    let left = bounds.iter().map(|b| b.0).min()?;
+    let top = bounds.iter().map(|b| b.1).min()?;
+    let right = bounds.iter().map(|b| b.2).max()?;
+    let bottom = bounds.iter().map(|b| b.3).max()?;
+    Some(CropArea::new(left, top, right, bottom))
+}
+
+// Samples frames of the given video stream and detects the active picture
+// area. Returns None if no usable frame could be decoded, or if all sampled
+// frames are black.
+pub fn detect_crop(input: &mut Input, index: usize) -> io::Result<Option<CropArea>> {
+    let timestamps = decode::sample_timestamps(input, SAMPLE_COUNT);
+    let mut bounds = Vec::new();
+    decode::sample_video_frames(input, index, &timestamps, |frame| {
+        if let Some(b) = LumaPlane::new(frame).and_then(|luma| active_bounds(&luma)) {
+            bounds.push(b);
+        }
+    })?;
+    Ok(merge_bounds(&bounds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn active_picture_bounds() {
+        // A 16x12 picture with 2-row letterbox bars and a 3-column bar on
+        // the left, and a dark (but not black) pixel in the active area.
+        let (width, height) = (16, 12);
+        let mut data = vec![16u8; width * height];
+        for y in 2..10 {
+            for x in 3..16 {
+                data[y * width + x] = 180;
+            }
+        }
+        data[5 * width + 8] = 20;
+        let luma = LumaPlane::gray8(&data, width, height);
+        assert_eq!(Some((3, 2, 15, 9)), active_bounds(&luma));
+
+        let black = vec![16u8; width * height];
+        assert_eq!(
+            None,
+            active_bounds(&LumaPlane::gray8(&black, width, height))
+        );
+    }
+
+    #[test]
+    fn merged_crop_area() {
+        // A 2.40:1 letterboxed picture in a 1920x1080 frame, one sample
+        // with a dark top edge and one with a dark bottom edge.
+        let area = merge_bounds(&[(0, 140, 1919, 900), (0, 150, 1919, 939)]).unwrap();
+        assert_eq!("1920x800+0+140", area.geometry);
+        assert_eq!(Rational(12, 5), area.aspect_ratio(Rational(1, 1)));
+        assert_eq!(None, merge_bounds(&[]));
+    }
+}
@@ -0,0 +1,376 @@
+// Helpers for analyses that need decoded frames rather than just stream
+// headers or packets.
+
+use crate::ffmpeg::codec::context::Context;
+use crate::ffmpeg::color;
+use crate::ffmpeg::decoder;
+use crate::ffmpeg::ffi;
+use crate::ffmpeg::format::context::Input;
+use crate::ffmpeg::format::sample::Type as SampleType;
+use crate::ffmpeg::format::Sample;
+use crate::ffmpeg::frame;
+use crate::ffmpeg::{Packet, Stream};
+use std::collections::HashMap;
+use std::io;
+use std::slice;
+
+const MAX_PACKETS_PER_SAMPLE: usize = 1024;
+
+// Streams without a usable decoder (e.g., FFmpeg built without one) are
+// not the file's fault, and are skipped rather than failing the analysis.
+pub fn open_decoder(stream: &Stream) -> Option<decoder::Opened> {
+    let codec = decoder::find(stream.parameters().id());
+    let decoder = codec.and_then(|c| {
+        Context::from_parameters(stream.parameters())
+            .ok()
+            .and_then(|context| context.decoder().open_as(c).ok())
+    });
+    if decoder.is_none() {
+        debug!("stream #{}: no usable decoder, skipped", stream.index());
+    }
+    decoder
+}
+
+enum Kind {
+    Audio,
+    Video,
+}
+
+// Decodes the given audio and video streams in a single pass over the
+// input, calling on_audio or on_video with the stream index and each
+// decoded frame. Packets that fail to decode are skipped.
+pub fn decode_audio_video<A, V>(
+    input: &mut Input,
+    audio_indices: &[usize],
+    video_indices: &[usize],
+    on_audio: A,
+    on_video: V,
+) -> io::Result<()>
+where
+    A: FnMut(usize, &frame::Audio),
+    V: FnMut(usize, &frame::Video),
+{
+    decode_packets(
+        input,
+        audio_indices,
+        video_indices,
+        |_, _| {},
+        on_audio,
+        on_video,
+    )
+}
+
+// Same as decode_audio_video, but also calls on_packet with the stream
+// index and each demuxed packet of any stream, before decoding it.
+pub fn decode_packets<P, A, V>(
+    input: &mut Input,
+    audio_indices: &[usize],
+    video_indices: &[usize],
+    mut on_packet: P,
+    mut on_audio: A,
+    mut on_video: V,
+) -> io::Result<()>
+where
+    P: FnMut(usize, &Packet),
+    A: FnMut(usize, &frame::Audio),
+    V: FnMut(usize, &frame::Video),
+{
+    let mut decoders: HashMap<usize, (decoder::Opened, Kind)> = HashMap::new();
+    let streams = audio_indices
+        .iter()
+        .map(|&i| (i, Kind::Audio))
+        .chain(video_indices.iter().map(|&i| (i, Kind::Video)));
+    for (index, kind) in streams {
+        if let Some(decoder) = input.stream(index).as_ref().and_then(open_decoder) {
+            decoders.insert(index, (decoder, kind));
+        }
+    }
+
+    let mut audio_frame = frame::Audio::empty();
+    let mut video_frame = frame::Video::empty();
+    let mut receive = |index: usize, decoder: &mut decoder::Opened, kind: &Kind| match kind {
+        Kind::Audio => {
+            while decoder.receive_frame(&mut audio_frame).is_ok() {
+                on_audio(index, &audio_frame);
+            }
+        }
+        Kind::Video => {
+            while decoder.receive_frame(&mut video_frame).is_ok() {
+                on_video(index, &video_frame);
+            }
+        }
+    };
+
+    for (stream, packet) in input.packets() {
+        let index = stream.index();
+        on_packet(index, &packet);
+        if let Some((decoder, kind)) = decoders.get_mut(&index) {
+            if let Err(e) = decoder.send_packet(&packet) {
+                debug!("stream #{}: failed to decode packet: {}", index, e);
+                continue;
+            }
+            receive(index, decoder, kind);
+        }
+    }
+
+    for (&index, (decoder, kind)) in decoders.iter_mut() {
+        if decoder.send_eof().is_ok() {
+            receive(index, decoder, kind);
+        }
+    }
+
+    Ok(())
+}
+
+pub fn decode_audio<F>(input: &mut Input, indices: &[usize], f: F) -> io::Result<()>
+where
+    F: FnMut(usize, &frame::Audio),
+{
+    decode_audio_video(input, indices, &[], f, |_, _| {})
+}
+
+pub fn decode_video<F>(input: &mut Input, indices: &[usize], f: F) -> io::Result<()>
+where
+    F: FnMut(usize, &frame::Video),
+{
+    decode_audio_video(input, &[], indices, |_, _| {}, f)
+}
+
+// Timestamps (in AV_TIME_BASE units) of count points evenly spaced
+// throughout the input, for use with sample_video_frames. Without a known
+// duration, only the beginning can be sampled reliably.
+pub fn sample_timestamps(input: &Input, count: i64) -> Vec<i64> {
+    let start_time = match unsafe { (*input.as_ptr()).start_time } {
+        ffi::AV_NOPTS_VALUE => 0,
+        t => t,
+    };
+    let duration = input.duration();
+    if duration > 0 {
+        (0..count)
+            .map(|i| start_time + duration * (2 * i + 1) / (2 * count))
+            .collect()
+    } else {
+        vec![start_time]
+    }
+}
+
+// Seeks to each of the given timestamps (in AV_TIME_BASE units) and calls
+// f with the first frame of the given video stream decoded from there. A
+// timestamp is given up on if no frame could be decoded from a reasonable
+// number of packets.
+pub fn sample_video_frames<F>(
+    input: &mut Input,
+    index: usize,
+    timestamps: &[i64],
+    mut f: F,
+) -> io::Result<()>
+where
+    F: FnMut(&frame::Video),
+{
+    let mut decoder = match input.stream(index).as_ref().and_then(open_decoder) {
+        Some(decoder) => decoder,
+        None => return Ok(()),
+    };
+
+    let mut frame = frame::Video::empty();
+    for &timestamp in timestamps {
+        if let Err(e) = input.seek(timestamp, ..timestamp) {
+            debug!("failed to seek to {}: {}", timestamp, e);
+        }
+        decoder.flush();
+        // Packets of all streams count towards the limit, lest a stream
+        // with few or no packets after the seek point read the rest of the
+        // file.
+        let mut packets = 0;
+        for (stream, packet) in input.packets() {
+            packets += 1;
+            if packets > MAX_PACKETS_PER_SAMPLE {
+                debug!("stream #{}: no frame decoded at {}", index, timestamp);
+                break;
+            }
+            if stream.index() != index {
+                continue;
+            }
+            if decoder.send_packet(&packet).is_ok() && decoder.receive_frame(&mut frame).is_ok() {
+                f(&frame);
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// The sample data of a decoded audio frame, one slice per channel for
+// planar formats, or a single slice of interleaved samples, without
+// padding. frame.data() cannot be used here: for audio, only the first
+// linesize is set, and planes beyond the eighth are only reachable through
+// extended_data.
+pub fn audio_planes(frame: &frame::Audio) -> Vec<&[u8]> {
+    let channels = frame.channels() as usize;
+    let sample_size = frame.format().bytes();
+    if frame.samples() == 0 || sample_size == 0 || frame.planes() == 0 {
+        return Vec::new();
+    }
+    let (plane_count, plane_size) = if frame.is_planar() {
+        (channels, frame.samples() * sample_size)
+    } else {
+        (1, frame.samples() * channels * sample_size)
+    };
+    (0..plane_count)
+        .map(|p| unsafe {
+            slice::from_raw_parts(*(*frame.as_ptr()).extended_data.add(p), plane_size)
+        })
+        .collect()
+}
+
+// Converts the samples of a decoded audio frame to floating point values in
+// [-1, 1], one vector per channel.
+pub fn audio_frame_samples(frame: &frame::Audio) -> Vec<Vec<f64>> {
+    let channels = frame.channels() as usize;
+    let samples = frame.samples();
+    let (sample_size, planar) = match frame.format() {
+        Sample::U8(t) => (1, t == SampleType::Planar),
+        Sample::I16(t) => (2, t == SampleType::Planar),
+        Sample::I32(t) | Sample::F32(t) => (4, t == SampleType::Planar),
+        Sample::I64(t) | Sample::F64(t) => (8, t == SampleType::Planar),
+        Sample::None => return vec![Vec::new(); channels],
+    };
+    let convert = |bytes: &[u8]| -> f64 {
+        match frame.format() {
+            Sample::U8(_) => (bytes[0] as f64 - 128f64) / 128f64,
+            Sample::I16(_) => i16::from_ne_bytes([bytes[0], bytes[1]]) as f64 / 32768f64,
+            Sample::I32(_) => {
+                let mut b = [0u8; 4];
+                b.copy_from_slice(bytes);
+                i32::from_ne_bytes(b) as f64 / 2147483648f64
+            }
+            Sample::I64(_) => {
+                let mut b = [0u8; 8];
+                b.copy_from_slice(bytes);
+                i64::from_ne_bytes(b) as f64 / 9223372036854775808f64
+            }
+            Sample::F32(_) => {
+                let mut b = [0u8; 4];
+                b.copy_from_slice(bytes);
+                f32::from_ne_bytes(b) as f64
+            }
+            Sample::F64(_) => {
+                let mut b = [0u8; 8];
+                b.copy_from_slice(bytes);
+                f64::from_ne_bytes(b)
+            }
+            Sample::None => 0f64,
+        }
+    };
+
+    let planes = audio_planes(frame);
+    if planes.is_empty() {
+        return vec![Vec::new(); channels];
+    }
+    (0..channels)
+        .map(|ch| {
+            (0..samples)
+                .map(|i| {
+                    let (plane, offset) = if planar {
+                        (ch, i * sample_size)
+                    } else {
+                        (0, (i * channels + ch) * sample_size)
+                    };
+                    convert(&planes[plane][offset..offset + sample_size])
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// The luma (or gray) plane of a decoded video frame, with sample values
+// normalized so that nominal black is 0 and nominal white is 1.
+pub struct LumaPlane<'a> {
+    data: &'a [u8],
+    stride: usize,
+    step: usize,
+    offset: usize,
+    shift: u32,
+    wide: bool,
+    big_endian: bool,
+    black: f64,
+    white: f64,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl<'a> LumaPlane<'a> {
+    // Returns None for formats without a luma plane, e.g., RGB and
+    // palette formats, and for hardware frames.
+    pub fn new(frame: &'a frame::Video) -> Option<LumaPlane<'a>> {
+        let descriptor = frame.format().descriptor()?;
+        let descriptor = unsafe { &*descriptor.as_ptr() };
+        let unsupported = (ffi::AV_PIX_FMT_FLAG_RGB
+            | ffi::AV_PIX_FMT_FLAG_PAL
+            | ffi::AV_PIX_FMT_FLAG_BITSTREAM
+            | ffi::AV_PIX_FMT_FLAG_HWACCEL) as u64;
+        let component = &descriptor.comp[0];
+        if descriptor.flags & unsupported != 0 || component.plane != 0 || frame.planes() == 0 {
+            return None;
+        }
+        let depth = component.depth as u32;
+        let (black, white) = if frame.color_range() == color::Range::JPEG {
+            (0f64, ((1u32 << depth) - 1) as f64)
+        } else if depth >= 8 {
+            (
+                (16u32 << (depth - 8)) as f64,
+                (235u32 << (depth - 8)) as f64,
+            )
+        } else {
+            (0f64, ((1u32 << depth) - 1) as f64)
+        };
+        Some(LumaPlane {
+            data: frame.data(0),
+            stride: frame.stride(0),
+            step: component.step as usize,
+            offset: component.offset as usize,
+            shift: component.shift as u32,
+            wide: depth > 8,
+            big_endian: descriptor.flags & ffi::AV_PIX_FMT_FLAG_BE as u64 != 0,
+            black,
+            white,
+            width: frame.width() as usize,
+            height: frame.height() as usize,
+        })
+    }
+
+    // An 8-bit limited range plane, for tests.
+    #[cfg(test)]
+    pub fn gray8(data: &'a [u8], width: usize, height: usize) -> LumaPlane<'a> {
+        LumaPlane {
+            data,
+            stride: width,
+            step: 1,
+            offset: 0,
+            shift: 0,
+            wide: false,
+            big_endian: false,
+            black: 16f64,
+            white: 235f64,
+            width,
+            height,
+        }
+    }
+
+    pub fn value(&self, x: usize, y: usize) -> f64 {
+        let i = y * self.stride + x * self.step + self.offset;
+        let raw = if self.wide {
+            let bytes = [self.data[i], self.data[i + 1]];
+            if self.big_endian {
+                u16::from_be_bytes(bytes)
+            } else {
+                u16::from_le_bytes(bytes)
+            }
+        } else {
+            self.data[i] as u16
+        };
+        let raw = raw >> self.shift;
+        (raw as f64 - self.black) / (self.white - self.black)
+    }
+}
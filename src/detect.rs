@@ -0,0 +1,355 @@
+// Detection of silence in audio streams and black or frozen pictures in
+// video streams, modeled after FFmpeg's silencedetect, blackdetect and
+// freezedetect filters.
+
+use crate::ffmpeg::format::context::Input;
+use crate::ffmpeg::format::stream::Disposition;
+use crate::ffmpeg::media::Type;
+use crate::ffmpeg::util::rational::Rational;
+use std::collections::HashMap;
+use std::io;
+
+use crate::decode::{self, LumaPlane};
+use crate::util;
+
+// Luma is sampled on a grid of at most this many points per dimension,
+// which is plenty for telling black or frozen pictures apart.
+const LUMA_GRID_SIZE: usize = 256;
+
+// Timestamps within this many seconds of the beginning or the end of a
+// stream are considered to be at the beginning or the end.
+const EDGE_TOLERANCE: f64 = 0.05;
+
+#[derive(Clone, Debug)]
+pub struct DetectionThresholds {
+    // Audio below this level (dBFS) in all channels is silence.
+    pub silence_noise: f64,
+    pub silence_duration: f64,
+    // Pixels with normalized luma not above this value are black.
+    pub black_pixel: f64,
+    // Pictures with at least this ratio of black pixels are black.
+    pub black_picture_ratio: f64,
+    pub black_duration: f64,
+    // Pictures with a mean absolute luma difference from the previous
+    // picture below this level (dB) are frozen.
+    pub freeze_noise: f64,
+    pub freeze_duration: f64,
+}
+
+impl Default for DetectionThresholds {
+    // Same as the defaults of the FFmpeg filters.
+    fn default() -> DetectionThresholds {
+        DetectionThresholds {
+            silence_noise: -60f64,
+            silence_duration: 2f64,
+            black_pixel: 0.1,
+            black_picture_ratio: 0.98,
+            black_duration: 2f64,
+            freeze_noise: -60f64,
+            freeze_duration: 2f64,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IntervalKind {
+    Silence,
+    Black,
+    Freeze,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IntervalPosition {
+    Leading,
+    Interior,
+    Trailing,
+    // The interval spans the entire stream.
+    Entire,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct DetectedInterval {
+    pub index: usize,
+    pub kind: IntervalKind,
+    pub position: IntervalPosition,
+    #[serde(skip_serializing)]
+    pub _start: f64,
+    pub start: String,
+    #[serde(skip_serializing)]
+    pub _end: f64,
+    pub end: String,
+    #[serde(skip_serializing)]
+    pub _duration: f64,
+    pub duration: String,
+}
+
+// Tracks runs of detected samples or pictures and keeps those lasting at
+// least the minimum duration.
+struct IntervalTracker {
+    min_duration: f64,
+    stream_start: Option<f64>,
+    run_start: Option<f64>,
+    intervals: Vec<(f64, f64)>,
+}
+
+impl IntervalTracker {
+    fn new(min_duration: f64) -> IntervalTracker {
+        IntervalTracker {
+            min_duration,
+            stream_start: None,
+            run_start: None,
+            intervals: Vec::new(),
+        }
+    }
+
+    // start is when the run would have started if detected is true;
+    // now is when the run ends otherwise.
+    fn update(&mut self, start: f64, now: f64, detected: bool) {
+        if self.stream_start.is_none() {
+            self.stream_start = Some(start);
+        }
+        if detected {
+            if self.run_start.is_none() {
+                self.run_start = Some(start);
+            }
+        } else if let Some(run_start) = self.run_start.take() {
+            if now - run_start >= self.min_duration {
+                self.intervals.push((run_start, now));
+            }
+        }
+    }
+
+    fn finish(
+        mut self,
+        index: usize,
+        kind: IntervalKind,
+        stream_end: f64,
+    ) -> Vec<DetectedInterval> {
+        self.update(stream_end, stream_end, false);
+        let stream_start = self.stream_start.unwrap_or(0f64);
+        self.intervals
+            .into_iter()
+            .map(|(start, end)| {
+                let leading = start - stream_start <= EDGE_TOLERANCE;
+                let trailing = stream_end - end <= EDGE_TOLERANCE;
+                DetectedInterval {
+                    index,
+                    kind,
+                    position: match (leading, trailing) {
+                        (true, true) => IntervalPosition::Entire,
+                        (true, false) => IntervalPosition::Leading,
+                        (false, true) => IntervalPosition::Trailing,
+                        (false, false) => IntervalPosition::Interior,
+                    },
+                    _start: start,
+                    start: util::format_seconds(start),
+                    _end: end,
+                    end: util::format_seconds(end),
+                    _duration: end - start,
+                    duration: format!("{:.3}s", end - start),
+                }
+            })
+            .collect()
+    }
+}
+
+struct SilenceDetector {
+    threshold: f64,
+    rate: f64,
+    start: f64,
+    samples: u64,
+    tracker: IntervalTracker,
+}
+
+impl SilenceDetector {
+    fn add_samples(&mut self, samples: &[Vec<f64>]) {
+        let length = samples.iter().map(Vec::len).min().unwrap_or(0);
+        for i in 0..length {
+            let silent = samples.iter().all(|c| c[i].abs() < self.threshold);
+            let now = self.start + self.samples as f64 / self.rate;
+            self.tracker.update(now, now, silent);
+            self.samples += 1;
+        }
+    }
+
+    fn finish(self, index: usize) -> Vec<DetectedInterval> {
+        let end = self.start + self.samples as f64 / self.rate;
+        self.tracker.finish(index, IntervalKind::Silence, end)
+    }
+}
+
+struct PictureDetector {
+    thresholds: DetectionThresholds,
+    time_base: f64,
+    frame_duration: f64,
+    previous: Option<(f64, Vec<f64>)>,
+    black: IntervalTracker,
+    freeze: IntervalTracker,
+}
+
+impl PictureDetector {
+    fn add_picture(&mut self, timestamp: Option<i64>, luma: &LumaPlane) {
+        let now = match (timestamp, &self.previous) {
+            (Some(ts), _) => ts as f64 * self.time_base,
+            (None, Some((previous, _))) => previous + self.frame_duration,
+            (None, None) => 0f64,
+        };
+
+        let x_step = (luma.width / LUMA_GRID_SIZE).max(1);
+        let y_step = (luma.height / LUMA_GRID_SIZE).max(1);
+        let samples = (0..luma.height)
+            .step_by(y_step)
+            .flat_map(|y| {
+                (0..luma.width)
+                    .step_by(x_step)
+                    .map(move |x| luma.value(x, y))
+            })
+            .collect::<Vec<_>>();
+        if samples.is_empty() {
+            return;
+        }
+
+        let black_pixels = samples
+            .iter()
+            .filter(|&&v| v <= self.thresholds.black_pixel)
+            .count();
+        let black =
+            black_pixels as f64 / samples.len() as f64 >= self.thresholds.black_picture_ratio;
+        self.black.update(now, now, black);
+
+        if let Some((previous, previous_samples)) = &self.previous {
+            let mafd = samples
+                .iter()
+                .zip(previous_samples.iter())
+                .map(|(a, b)| (a - b).abs())
+                .sum::<f64>()
+                / samples.len() as f64;
+            let frozen = mafd <= 10f64.powf(self.thresholds.freeze_noise / 20f64);
+            self.freeze.update(*previous, now, frozen);
+            if now > *previous {
+                self.frame_duration = now - previous;
+            }
+        }
+        self.previous = Some((now, samples));
+    }
+
+    fn finish(self, index: usize) -> Vec<DetectedInterval> {
+        let end = self.previous.map_or(0f64, |(t, _)| t + self.frame_duration);
+        let mut intervals = self.black.finish(index, IntervalKind::Black, end);
+        intervals.extend(self.freeze.finish(index, IntervalKind::Freeze, end));
+        intervals
+    }
+}
+
+// Decodes all audio and video streams of the input (attached pictures
+// excluded) in a single pass and detects silence, black pictures and
+// frozen pictures.
+pub fn detect_intervals(
+    input: &mut Input,
+    thresholds: &DetectionThresholds,
+) -> io::Result<Vec<DetectedInterval>> {
+    let mut audio_indices = Vec::new();
+    let mut video_indices = Vec::new();
+    let mut time_bases: HashMap<usize, Rational> = HashMap::new();
+    let mut frame_durations: HashMap<usize, f64> = HashMap::new();
+    for stream in input.streams() {
+        match stream.parameters().medium() {
+            Type::Audio => audio_indices.push(stream.index()),
+            Type::Video if !stream.disposition().contains(Disposition::ATTACHED_PIC) => {
+                video_indices.push(stream.index());
+                let frame_rate = stream.avg_frame_rate();
+                if frame_rate.numerator() > 0 && frame_rate.denominator() > 0 {
+                    frame_durations.insert(stream.index(), f64::from(frame_rate.invert()));
+                }
+            }
+            _ => continue,
+        }
+        time_bases.insert(stream.index(), stream.time_base());
+    }
+
+    let mut silence_detectors: HashMap<usize, SilenceDetector> = HashMap::new();
+    let mut picture_detectors: HashMap<usize, PictureDetector> = HashMap::new();
+    decode::decode_audio_video(
+        input,
+        &audio_indices,
+        &video_indices,
+        |index, frame| {
+            let detector = silence_detectors
+                .entry(index)
+                .or_insert_with(|| SilenceDetector {
+                    threshold: 10f64.powf(thresholds.silence_noise / 20f64),
+                    rate: frame.rate() as f64,
+                    start: frame
+                        .timestamp()
+                        .map_or(0f64, |ts| ts as f64 * f64::from(time_bases[&index])),
+                    samples: 0,
+                    tracker: IntervalTracker::new(thresholds.silence_duration),
+                });
+            detector.add_samples(&decode::audio_frame_samples(frame));
+        },
+        |index, frame| {
+            if let Some(luma) = LumaPlane::new(frame) {
+                let detector = picture_detectors
+                    .entry(index)
+                    .or_insert_with(|| PictureDetector {
+                        thresholds: thresholds.clone(),
+                        time_base: f64::from(time_bases[&index]),
+                        frame_duration: frame_durations.get(&index).cloned().unwrap_or(0f64),
+                        previous: None,
+                        black: IntervalTracker::new(thresholds.black_duration),
+                        freeze: IntervalTracker::new(thresholds.freeze_duration),
+                    });
+                detector.add_picture(frame.timestamp(), &luma);
+            }
+        },
+    )?;
+
+    let mut intervals = Vec::new();
+    for (index, detector) in silence_detectors {
+        intervals.extend(detector.finish(index));
+    }
+    for (index, detector) in picture_detectors {
+        intervals.extend(detector.finish(index));
+    }
+
+    intervals.sort_by(|a, b| {
+        (a.index, a._start)
+            .partial_cmp(&(b.index, b._start))
+            .unwrap()
+    });
+    Ok(intervals)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn silence_intervals_by_position() {
+        let rate = 1000;
+        let mut detector = SilenceDetector {
+            threshold: 10f64.powf(-60f64 / 20f64),
+            rate: rate as f64,
+            start: 0f64,
+            samples: 0,
+            tracker: IntervalTracker::new(2f64),
+        };
+        // 3s silence, 1s tone, 1s silence (too short), 1s tone, 2.5s
+        // silence.
+        let mut samples = vec![0f64; rate * 3];
+        samples.extend(vec![0.5; rate]);
+        samples.extend(vec![0f64; rate]);
+        samples.extend(vec![0.5; rate]);
+        samples.extend(vec![0f64; rate * 5 / 2]);
+        detector.add_samples(&[samples]);
+        let intervals = detector.finish(0);
+        assert_eq!(2, intervals.len());
+        assert_eq!(IntervalPosition::Leading, intervals[0].position);
+        assert_eq!(3f64, intervals[0]._duration);
+        assert_eq!(IntervalPosition::Trailing, intervals[1].position);
+        assert_eq!(6f64, intervals[1]._start);
+        assert_eq!(8.5, intervals[1]._end);
+    }
+}
@@ -0,0 +1,408 @@
+// Tag editing by stream-copy remuxing to a new file. Codecs are left
+// untouched; only container-level and stream-level metadata (tags,
+// languages and dispositions) change.
+
+use crate::ffmpeg;
+use crate::ffmpeg::format::context::{Input, Output};
+use crate::ffmpeg::{ffi, Dictionary, Rational};
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::media_file::MediaFileMetadata;
+use crate::tags::{Tags, ToTags};
+
+// Container tags libavformat writes itself unless asked not to (e.g., as
+// ©too in MP4), so removing them cannot be verified.
+const MUXER_WRITTEN_TAGS: [&str; 1] = ["encoder"];
+
+#[derive(Clone, Debug)]
+enum Edit {
+    // None for container-level tags.
+    SetTag {
+        stream: Option<usize>,
+        key: String,
+        value: String,
+    },
+    RemoveTag {
+        stream: Option<usize>,
+        key: String,
+    },
+    Disposition {
+        stream: usize,
+        name: String,
+        flag: i32,
+        on: bool,
+    },
+}
+
+// Edits are applied in the order given, so later ones override earlier
+// ones.
+#[derive(Clone, Debug, Default)]
+pub struct TagEdits {
+    edits: Vec<Edit>,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn describe_target(stream: Option<usize>) -> String {
+    match stream {
+        Some(i) => format!("stream #{}", i),
+        None => "container".to_string(),
+    }
+}
+
+impl TagEdits {
+    pub fn new() -> TagEdits {
+        TagEdits::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.edits.is_empty()
+    }
+
+    // Sets a container tag if stream is None, otherwise a tag of the stream.
+    pub fn set_tag(&mut self, stream: Option<usize>, key: &str, value: &str) -> &mut TagEdits {
+        self.edits.push(Edit::SetTag {
+            stream,
+            key: key.to_string(),
+            value: value.to_string(),
+        });
+        self
+    }
+
+    pub fn remove_tag(&mut self, stream: Option<usize>, key: &str) -> &mut TagEdits {
+        self.edits.push(Edit::RemoveTag {
+            stream,
+            key: key.to_string(),
+        });
+        self
+    }
+
+    // Languages are ISO 639-2 codes, e.g., "eng", stored as a stream tag.
+    pub fn set_language(&mut self, stream: usize, language: &str) -> &mut TagEdits {
+        self.set_tag(Some(stream), "language", language)
+    }
+
+    // Sets or clears a disposition flag by its libavformat name, e.g.,
+    // "default", "forced" or "hearing_impaired".
+    pub fn set_disposition(
+        &mut self,
+        stream: usize,
+        name: &str,
+        on: bool,
+    ) -> io::Result<&mut TagEdits> {
+        let c_name = CString::new(name).map_err(|_| invalid("invalid disposition".to_string()))?;
+        let flag = unsafe { ffi::av_disposition_from_string(c_name.as_ptr()) };
+        if flag < 0 {
+            return Err(invalid(format!("unknown disposition \"{}\"", name)));
+        }
+        self.edits.push(Edit::Disposition {
+            stream,
+            name: name.to_string(),
+            flag,
+            on,
+        });
+        Ok(self)
+    }
+
+    fn edited_tags(&self, stream: Option<usize>, tags: Tags) -> Tags {
+        let mut tags = tags;
+        for edit in &self.edits {
+            match edit {
+                Edit::SetTag {
+                    stream: s,
+                    key,
+                    value,
+                } if *s == stream => {
+                    match tags.iter_mut().find(|(k, _)| k.eq_ignore_ascii_case(key)) {
+                        Some(tag) => tag.1 = value.clone(),
+                        None => tags.push((key.clone(), value.clone())),
+                    }
+                }
+                Edit::RemoveTag { stream: s, key } if *s == stream => {
+                    tags.retain(|(k, _)| !k.eq_ignore_ascii_case(key))
+                }
+                _ => (),
+            }
+        }
+        tags
+    }
+
+    fn edited_disposition(&self, stream: usize, disposition: i32) -> i32 {
+        self.edits
+            .iter()
+            .fold(disposition, |disposition, edit| match edit {
+                Edit::Disposition {
+                    stream: s,
+                    flag,
+                    on,
+                    ..
+                } if *s == stream => {
+                    if *on {
+                        disposition | flag
+                    } else {
+                        disposition & !flag
+                    }
+                }
+                _ => disposition,
+            })
+    }
+
+    // The final expected value of each edited tag (None if removed), keyed
+    // by target and lowercased key.
+    fn expected_tags(&self) -> HashMap<(Option<usize>, String), Option<String>> {
+        let mut expected = HashMap::new();
+        for edit in &self.edits {
+            match edit {
+                Edit::SetTag { stream, key, value } => {
+                    expected.insert((*stream, key.to_ascii_lowercase()), Some(value.clone()));
+                }
+                Edit::RemoveTag { stream, key } => {
+                    expected.insert((*stream, key.to_ascii_lowercase()), None);
+                }
+                _ => (),
+            }
+        }
+        expected
+    }
+
+    fn check_streams(&self, nb_streams: usize) -> io::Result<()> {
+        for edit in &self.edits {
+            let stream = match edit {
+                Edit::SetTag { stream, .. } | Edit::RemoveTag { stream, .. } => *stream,
+                Edit::Disposition { stream, .. } => Some(*stream),
+            };
+            if let Some(i) = stream.filter(|&i| i >= nb_streams) {
+                return Err(invalid(format!("no stream #{} in input", i)));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn to_dictionary(tags: &Tags) -> Dictionary<'static> {
+    let mut dictionary = Dictionary::new();
+    for (key, value) in tags {
+        dictionary.set(key, value);
+    }
+    dictionary
+}
+
+// Copies all streams and chapters of the input to the output with edited
+// metadata, without decoding.
+fn remux(input: &mut Input, output: &mut Output, edits: &TagEdits) -> io::Result<()> {
+    let output_format = output.format();
+    let mut input_time_bases = Vec::new();
+    for stream in input.streams() {
+        let index = stream.index();
+        let tags = edits.edited_tags(Some(index), stream.metadata().to_tags());
+        let disposition =
+            edits.edited_disposition(index, unsafe { (*stream.as_ptr()).disposition });
+        let mut out_stream = output.add_stream(ffmpeg::encoder::find(ffmpeg::codec::Id::None))?;
+        out_stream.set_parameters(stream.parameters());
+        out_stream.set_time_base(stream.time_base());
+        out_stream.set_metadata(to_dictionary(&tags));
+        unsafe {
+            let out_ptr = out_stream.as_mut_ptr();
+            (*out_ptr).disposition = disposition;
+            // The codec tag is kept only if the output format knows it for
+            // the codec (e.g., hvc1 vs hev1 for HEVC in MP4), lest muxing
+            // into a different container fails.
+            let par = (*out_ptr).codecpar;
+            let oformat = output_format.as_ptr();
+            if (*par).codec_tag != 0
+                && ((*oformat).codec_tag.is_null()
+                    || ffi::av_codec_get_id((*oformat).codec_tag, (*par).codec_tag)
+                        != (*par).codec_id)
+            {
+                (*par).codec_tag = 0;
+            }
+        }
+        input_time_bases.push(stream.time_base());
+    }
+    for chapter in input.chapters() {
+        output.add_chapter(
+            chapter.id(),
+            chapter.time_base(),
+            chapter.start(),
+            chapter.end(),
+            chapter.metadata().get("title").unwrap_or(""),
+        )?;
+    }
+    output.set_metadata(to_dictionary(
+        &edits.edited_tags(None, input.metadata().to_tags()),
+    ));
+
+    output.write_header()?;
+    // Muxers may change time bases in write_header.
+    let output_time_bases: Vec<Rational> = (0..input_time_bases.len())
+        .map(|i| {
+            output
+                .stream(i)
+                .map_or(input_time_bases[i], |s| s.time_base())
+        })
+        .collect();
+    for (stream, mut packet) in input.packets() {
+        let index = stream.index();
+        packet.rescale_ts(input_time_bases[index], output_time_bases[index]);
+        packet.set_position(-1);
+        packet.set_stream(index);
+        packet.write_interleaved(output)?;
+    }
+    output.write_trailer()?;
+    Ok(())
+}
+
+// Re-probes the output and checks that codecs are unchanged and that the
+// edits survived muxing (not all muxers support arbitrary tags).
+fn verify(input: &Input, output_path: &Path, edits: &TagEdits) -> io::Result<MediaFileMetadata> {
+    let meta = MediaFileMetadata::new(&output_path)?;
+    let output = ffmpeg::format::input(&output_path)?;
+    let mut problems = Vec::new();
+
+    if output.streams().count() != input.streams().count() {
+        problems.push(format!(
+            "{} streams in output, {} in input",
+            output.streams().count(),
+            input.streams().count()
+        ));
+    }
+    for (in_stream, out_stream) in input.streams().zip(output.streams()) {
+        let index = in_stream.index();
+        if in_stream.parameters().id() != out_stream.parameters().id() {
+            problems.push(format!("codec of stream #{} changed", index));
+        }
+        let expected =
+            edits.edited_disposition(index, unsafe { (*in_stream.as_ptr()).disposition });
+        let actual = unsafe { (*out_stream.as_ptr()).disposition };
+        for edit in &edits.edits {
+            if let Edit::Disposition {
+                stream, name, flag, ..
+            } = edit
+            {
+                if *stream == index && (expected & flag) != (actual & flag) {
+                    problems.push(format!(
+                        "disposition \"{}\" of stream #{} not preserved",
+                        name, index
+                    ));
+                }
+            }
+        }
+    }
+
+    let mut expected_tags = edits.expected_tags().into_iter().collect::<Vec<_>>();
+    expected_tags.sort();
+    for ((stream, key), value) in expected_tags {
+        if stream.is_none() && value.is_none() && MUXER_WRITTEN_TAGS.contains(&key.as_str()) {
+            continue;
+        }
+        let tags = match stream {
+            Some(i) => meta.streams_tags.get(i).map(|s| &s.tags),
+            None => Some(&meta.tags),
+        };
+        let actual = tags.and_then(|tags| {
+            tags.iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(&key))
+                .map(|(_, v)| v)
+        });
+        if actual != value.as_ref() {
+            problems.push(match value {
+                Some(_) => format!(
+                    "tag \"{}\" of {} not written (unsupported by the muxer?)",
+                    key,
+                    describe_target(stream)
+                ),
+                None => format!("tag \"{}\" of {} not removed", key, describe_target(stream)),
+            });
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(meta)
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "\"{}\" not kept, as {}",
+                output_path.display(),
+                problems.join("; ")
+            ),
+        ))
+    }
+}
+
+// Writes a copy of the input with the edits applied to the output path,
+// whose format is guessed from its extension, and returns the metadata of
+// the result. The output is never the input itself, and is removed again
+// if remuxing fails or the edits did not survive.
+pub fn write_tags(
+    input_path: &Path,
+    output_path: &Path,
+    edits: &TagEdits,
+) -> io::Result<MediaFileMetadata> {
+    if output_path.exists() && input_path.canonicalize()? == output_path.canonicalize()? {
+        return Err(invalid(
+            "cannot edit tags in place, the output must be a different file".to_string(),
+        ));
+    }
+    let mut input = ffmpeg::format::input(&input_path)?;
+    edits.check_streams(input.streams().count())?;
+    let mut output = ffmpeg::format::output(&output_path)?;
+    let result = remux(&mut input, &mut output, edits);
+    drop(output);
+    let result = result.and_then(|()| verify(&input, output_path, edits));
+    if result.is_err() {
+        if let Err(e) = fs::remove_file(output_path) {
+            debug!("failed to remove \"{}\": {}", output_path.display(), e);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(pairs: &[(&str, &str)]) -> Tags {
+        pairs
+            .iter()
+            .map(|&(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn edit_tags() {
+        let mut edits = TagEdits::new();
+        edits
+            .set_tag(None, "title", "New title")
+            .set_tag(None, "comment", "Added")
+            .remove_tag(None, "ENCODER")
+            .set_language(1, "jpn");
+        assert_eq!(
+            tags(&[("TITLE", "New title"), ("comment", "Added")]),
+            edits.edited_tags(None, tags(&[("TITLE", "Old"), ("encoder", "Lavf")]))
+        );
+        assert_eq!(
+            tags(&[("language", "jpn")]),
+            edits.edited_tags(Some(1), tags(&[("language", "und")]))
+        );
+        assert_eq!(
+            tags(&[("language", "und")]),
+            edits.edited_tags(Some(0), tags(&[("language", "und")]))
+        );
+        assert!(edits.check_streams(2).is_ok());
+        assert!(edits.check_streams(1).is_err());
+
+        let expected = edits.expected_tags();
+        assert_eq!(None, expected[&(None, "encoder".to_string())]);
+        assert_eq!(
+            Some("jpn".to_string()),
+            expected[&(Some(1), "language".to_string())]
+        );
+    }
+}
@@ -0,0 +1,281 @@
+// Predicates on metadata for selecting files, e.g., "height>=1080" or
+// "tag:artist~=Beatles".
+
+use regex::{Regex, RegexBuilder};
+use std::io;
+use std::str::FromStr;
+
+use crate::media_file::MediaFileMetadata;
+use crate::stream::StreamMetadata;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operator {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    // Case-insensitive regex search.
+    Matches,
+    NotMatches,
+}
+
+// Two-character operators come first so that they take precedence over
+// their one-character prefixes at the same position.
+const OPERATORS: [(&str, Operator); 8] = [
+    ("~=", Operator::Matches),
+    ("!~", Operator::NotMatches),
+    ("!=", Operator::Ne),
+    ("<=", Operator::Le),
+    (">=", Operator::Ge),
+    ("=", Operator::Eq),
+    ("<", Operator::Lt),
+    (">", Operator::Gt),
+];
+
+// All fields are known right after MediaFileMetadata::new, before any
+// analysis.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Field {
+    // Any container or stream tag with the key (case-insensitive), or the
+    // normalized tag of the same name.
+    Tag(String),
+    Title,
+    Container,
+    // Codec of any stream, either the description or the FFmpeg name.
+    Codec,
+    // Language of any audio or subtitle stream.
+    Language,
+    Duration,
+    Width,
+    Height,
+    FrameRate,
+    BitRate,
+    Size,
+}
+
+impl Field {
+    fn is_numeric(&self) -> bool {
+        matches!(
+            self,
+            Field::Duration
+                | Field::Width
+                | Field::Height
+                | Field::FrameRate
+                | Field::BitRate
+                | Field::Size
+        )
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Predicate {
+    pub field: Field,
+    pub operator: Operator,
+    pub value: String,
+    regex: Option<Regex>,
+    number: Option<f64>,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+// Parses seconds, optionally as [[HH:]MM:]SS.
+fn parse_seconds(s: &str) -> Option<f64> {
+    s.split(':').try_fold(0f64, |acc, part| {
+        Some(acc * 60f64 + part.parse::<f64>().ok()?)
+    })
+}
+
+impl FromStr for Predicate {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> io::Result<Predicate> {
+        // The leftmost operator splits the field and the value.
+        let (position, symbol, operator) = OPERATORS
+            .iter()
+            .filter_map(|&(symbol, operator)| s.find(symbol).map(|i| (i, symbol, operator)))
+            .min_by_key(|&(i, _, _)| i)
+            .ok_or_else(|| invalid(format!("no operator in predicate \"{}\"", s)))?;
+        let name = s[..position].trim();
+        let value = s[position + symbol.len()..].trim().to_string();
+
+        let field = match name.to_ascii_lowercase().as_str() {
+            "title" => Field::Title,
+            "container" | "format" => Field::Container,
+            "codec" => Field::Codec,
+            "language" | "lang" => Field::Language,
+            "duration" => Field::Duration,
+            "width" => Field::Width,
+            "height" => Field::Height,
+            "frame_rate" | "fps" => Field::FrameRate,
+            "bit_rate" | "bitrate" => Field::BitRate,
+            "size" => Field::Size,
+            _ => match name.split_once(':') {
+                Some((prefix, key)) if prefix.eq_ignore_ascii_case("tag") && !key.is_empty() => {
+                    Field::Tag(key.to_string())
+                }
+                _ => return Err(invalid(format!("unknown field \"{}\"", name))),
+            },
+        };
+
+        let regex = match operator {
+            Operator::Matches | Operator::NotMatches => Some(
+                RegexBuilder::new(&value)
+                    .case_insensitive(true)
+                    .build()
+                    .map_err(|e| invalid(format!("invalid regex \"{}\": {}", value, e)))?,
+            ),
+            _ => None,
+        };
+        let number = if field == Field::Duration {
+            parse_seconds(&value)
+        } else {
+            value.parse::<f64>().ok()
+        };
+        let ordering = matches!(
+            operator,
+            Operator::Lt | Operator::Le | Operator::Gt | Operator::Ge
+        );
+        if (ordering || field.is_numeric()) && regex.is_none() && number.is_none() {
+            return Err(invalid(format!("expected a number in predicate \"{}\"", s)));
+        }
+
+        Ok(Predicate {
+            field,
+            operator,
+            value,
+            regex,
+            number,
+        })
+    }
+}
+
+impl Predicate {
+    // Whether a single value satisfies the predicate. Numbers are compared
+    // numerically, anything else case-insensitively as strings.
+    fn test(&self, value: &str) -> bool {
+        if let Some(regex) = &self.regex {
+            return regex.is_match(value) == (self.operator == Operator::Matches);
+        }
+        let actual = if self.field == Field::Duration {
+            parse_seconds(value)
+        } else {
+            value.parse::<f64>().ok()
+        };
+        match (self.operator, actual, self.number) {
+            (Operator::Eq, Some(a), Some(b)) => a == b,
+            (Operator::Ne, Some(a), Some(b)) => a != b,
+            (Operator::Eq, _, _) => value.eq_ignore_ascii_case(&self.value),
+            (Operator::Ne, _, _) => !value.eq_ignore_ascii_case(&self.value),
+            (Operator::Lt, Some(a), Some(b)) => a < b,
+            (Operator::Le, Some(a), Some(b)) => a <= b,
+            (Operator::Gt, Some(a), Some(b)) => a > b,
+            (Operator::Ge, Some(a), Some(b)) => a >= b,
+            _ => false,
+        }
+    }
+
+    fn values(&self, m: &MediaFileMetadata) -> Vec<String> {
+        let streams = &m._streams_metadata;
+        match &self.field {
+            Field::Tag(key) => {
+                let mut values = m
+                    .tags
+                    .iter()
+                    .chain(m.streams_tags.iter().flat_map(|s| s.tags.iter()))
+                    .filter(|(k, _)| k.eq_ignore_ascii_case(key))
+                    .map(|(_, v)| v.clone())
+                    .collect::<Vec<_>>();
+                values.extend(m.normalized_tags.get(key).map(str::to_string));
+                values
+            }
+            Field::Title => m.title.iter().cloned().collect(),
+            Field::Container => vec![m.container_format.clone()],
+            Field::Codec => streams
+                .iter()
+                .flat_map(|s| match s {
+                    StreamMetadata::VideoMetadata(v) => {
+                        vec![v.codec_desc.clone(), v._codec.name().to_string()]
+                    }
+                    StreamMetadata::AudioMetadata(a) => {
+                        vec![a.codec_desc.clone(), a._codec.name().to_string()]
+                    }
+                    StreamMetadata::SubtitleMetadata(s) => {
+                        vec![s.codec_desc.clone(), s._codec.name().to_string()]
+                    }
+                    _ => Vec::new(),
+                })
+                .collect(),
+            Field::Language => streams
+                .iter()
+                .filter_map(|s| match s {
+                    StreamMetadata::AudioMetadata(a) => a.language.clone(),
+                    StreamMetadata::SubtitleMetadata(s) => s.language.clone(),
+                    _ => None,
+                })
+                .collect(),
+            Field::Duration => m._duration.iter().map(|d| d.to_string()).collect(),
+            Field::Width => m.width.iter().map(|w| w.to_string()).collect(),
+            Field::Height => m.height.iter().map(|h| h.to_string()).collect(),
+            Field::FrameRate => m
+                ._frame_rate
+                .iter()
+                .map(|&r| f64::from(r).to_string())
+                .collect(),
+            Field::BitRate => m._bit_rate.iter().map(|b| b.to_string()).collect(),
+            Field::Size => vec![m.file_size.to_string()],
+        }
+    }
+
+    // Multi-valued fields (tags, codecs, languages) match if any value
+    // does, except for negative operators, which require all values to.
+    // Missing fields only satisfy negative operators.
+    pub fn matches(&self, m: &MediaFileMetadata) -> bool {
+        let values = self.values(m);
+        match self.operator {
+            Operator::Ne | Operator::NotMatches => values.iter().all(|v| self.test(v)),
+            _ => values.iter().any(|v| self.test(v)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_predicates() {
+        let p = "tag:artist~=Beatles".parse::<Predicate>().unwrap();
+        assert_eq!(Field::Tag("artist".to_string()), p.field);
+        assert_eq!(Operator::Matches, p.operator);
+        assert_eq!("Beatles", p.value);
+        let p = "height >= 1080".parse::<Predicate>().unwrap();
+        assert_eq!(Field::Height, p.field);
+        assert_eq!(Operator::Ge, p.operator);
+        assert_eq!(Some(1080f64), p.number);
+        let p = "duration<1:30".parse::<Predicate>().unwrap();
+        assert_eq!(Some(90f64), p.number);
+        assert!("height".parse::<Predicate>().is_err());
+        assert!("depth=8".parse::<Predicate>().is_err());
+        assert!("width>wide".parse::<Predicate>().is_err());
+        assert!("codec~=(".parse::<Predicate>().is_err());
+    }
+
+    #[test]
+    fn test_values() {
+        let p = "tag:artist~=beatles".parse::<Predicate>().unwrap();
+        assert!(p.test("The Beatles"));
+        assert!(!p.test("The Rolling Stones"));
+        let p = "language!=eng".parse::<Predicate>().unwrap();
+        assert!(!p.test("ENG"));
+        assert!(p.test("jpn"));
+        let p = "width>1280".parse::<Predicate>().unwrap();
+        assert!(p.test("1920"));
+        assert!(!p.test("1280"));
+        let p = "duration>=90".parse::<Predicate>().unwrap();
+        assert!(p.test("90.5"));
+    }
+}
@@ -0,0 +1,279 @@
+use crate::ffmpeg::format::context::Input;
+use crate::ffmpeg::format::stream::Disposition;
+use crate::ffmpeg::media::Type;
+use crate::ffmpeg::util::rational::Rational;
+use std::collections::HashMap;
+use std::io;
+
+use crate::util;
+
+// Number of leading keyframes whose positions are reported.
+const FIRST_KEYFRAMES_COUNT: usize = 5;
+
+#[derive(Clone, Copy, Debug)]
+pub struct PacketInfo {
+    pub is_key: bool,
+    pub pts: Option<i64>,
+    pub dts: Option<i64>,
+    pub duration: i64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct KeyframePosition {
+    // Zero-based frame number in decoding order.
+    pub frame: usize,
+    #[serde(skip_serializing)]
+    pub _time: Option<f64>,
+    pub time: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct GopStructure {
+    pub index: usize,
+
+    pub frame_count: usize,
+    pub gop_count: usize,
+
+    pub min_frames: Option<usize>,
+    pub max_frames: Option<usize>,
+    #[serde(skip_serializing)]
+    pub _average_frames: Option<f64>,
+    pub average_frames: Option<String>,
+
+    #[serde(skip_serializing)]
+    pub _min_duration: Option<f64>,
+    pub min_duration: Option<String>,
+    #[serde(skip_serializing)]
+    pub _max_duration: Option<f64>,
+    pub max_duration: Option<String>,
+    #[serde(skip_serializing)]
+    pub _average_duration: Option<f64>,
+    pub average_duration: Option<String>,
+
+    // None if timestamps are not available to tell.
+    pub closed_gops: Option<bool>,
+    pub b_frames: bool,
+
+    pub first_keyframes: Vec<KeyframePosition>,
+}
+
+fn format_duration(secs: f64) -> String {
+    format!("{:.3}s", secs)
+}
+
+impl GopStructure {
+    // Packets are expected in decoding order.
+    pub fn from_packets(index: usize, time_base: Rational, packets: &[PacketInfo]) -> GopStructure {
+        let time_base = f64::from(time_base);
+        let frame_count = packets.len();
+
+        // A new GOP starts at each keyframe. Leading packets before the
+        // first keyframe (if any) are not counted towards any GOP.
+        let keyframe_positions = packets
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| p.is_key)
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        let gop_count = keyframe_positions.len();
+
+        // End of the stream in presentation time, for the duration of
+        // the last GOP.
+        let stream_end = packets
+            .iter()
+            .filter_map(|p| p.pts.map(|pts| pts + p.duration.max(0)))
+            .max();
+
+        let mut gop_frames = Vec::new();
+        let mut gop_durations = Vec::new();
+        let mut closed_gops = None;
+        for (n, &start) in keyframe_positions.iter().enumerate() {
+            let end = keyframe_positions
+                .get(n + 1)
+                .cloned()
+                .unwrap_or(frame_count);
+            gop_frames.push(end - start);
+
+            let key_pts = packets[start].pts;
+            let next_pts = match keyframe_positions.get(n + 1) {
+                Some(&next) => packets[next].pts,
+                None => stream_end,
+            };
+            if let (Some(key_pts), Some(next_pts)) = (key_pts, next_pts) {
+                gop_durations.push((next_pts - key_pts) as f64 * time_base);
+            }
+
+            // A GOP is open if any of its pictures is presented before
+            // the keyframe, i.e., references pictures of the previous
+            // GOP.
+            if let Some(key_pts) = key_pts {
+                let open = packets[start + 1..end]
+                    .iter()
+                    .any(|p| p.pts.is_some_and(|pts| pts < key_pts));
+                closed_gops = Some(closed_gops.unwrap_or(true) && !open);
+            }
+        }
+
+        // Presentation order differing from decoding order is a sure sign
+        // of B-frames.
+        let mut b_frames = false;
+        let mut max_pts = None;
+        for pts in packets.iter().filter_map(|p| p.pts) {
+            if max_pts.is_some_and(|max| pts < max) {
+                b_frames = true;
+                break;
+            }
+            max_pts = Some(pts);
+        }
+
+        let min_frames = gop_frames.iter().min().cloned();
+        let max_frames = gop_frames.iter().max().cloned();
+        let _average_frames = if gop_frames.is_empty() {
+            None
+        } else {
+            Some(gop_frames.iter().sum::<usize>() as f64 / gop_frames.len() as f64)
+        };
+        let average_frames = _average_frames.map(|f| format!("{:.2}", f));
+
+        let _min_duration = gop_durations
+            .iter()
+            .cloned()
+            .fold(None, |m: Option<f64>, d| Some(m.map_or(d, |m| m.min(d))));
+        let _max_duration = gop_durations
+            .iter()
+            .cloned()
+            .fold(None, |m: Option<f64>, d| Some(m.map_or(d, |m| m.max(d))));
+        let _average_duration = if gop_durations.is_empty() {
+            None
+        } else {
+            Some(gop_durations.iter().sum::<f64>() / gop_durations.len() as f64)
+        };
+
+        let first_keyframes = keyframe_positions
+            .iter()
+            .take(FIRST_KEYFRAMES_COUNT)
+            .map(|&i| {
+                let _time = packets[i].pts.map(|pts| pts as f64 * time_base);
+                KeyframePosition {
+                    frame: i,
+                    _time,
+                    time: _time.map(util::format_seconds),
+                }
+            })
+            .collect();
+
+        GopStructure {
+            index,
+            frame_count,
+            gop_count,
+            min_frames,
+            max_frames,
+            _average_frames,
+            average_frames,
+            _min_duration,
+            min_duration: _min_duration.map(format_duration),
+            _max_duration,
+            max_duration: _max_duration.map(format_duration),
+            _average_duration,
+            average_duration: _average_duration.map(format_duration),
+            closed_gops,
+            b_frames,
+            first_keyframes,
+        }
+    }
+}
+
+// Walks all packets of the input and analyzes the GOP structure of each
+// video stream. Attached pictures (cover art) are skipped.
+pub fn analyze_gops(input: &mut Input) -> io::Result<Vec<GopStructure>> {
+    let mut time_bases = HashMap::new();
+    let mut packets: HashMap<usize, Vec<PacketInfo>> = HashMap::new();
+    for stream in input.streams() {
+        if stream.parameters().medium() == Type::Video
+            && !stream.disposition().contains(Disposition::ATTACHED_PIC)
+        {
+            time_bases.insert(stream.index(), stream.time_base());
+            packets.insert(stream.index(), Vec::new());
+        }
+    }
+
+    for (stream, packet) in input.packets() {
+        if let Some(stream_packets) = packets.get_mut(&stream.index()) {
+            stream_packets.push(PacketInfo {
+                is_key: packet.is_key(),
+                pts: packet.pts(),
+                dts: packet.dts(),
+                duration: packet.duration(),
+            });
+        }
+    }
+
+    let mut indices = packets.keys().cloned().collect::<Vec<_>>();
+    indices.sort();
+    Ok(indices
+        .into_iter()
+        .map(|i| {
+            debug!("stream #{}: {} video packets", i, packets[&i].len());
+            GopStructure::from_packets(i, time_bases[&i], &packets[&i])
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(is_key: bool, pts: i64, dts: i64) -> PacketInfo {
+        PacketInfo {
+            is_key,
+            pts: Some(pts),
+            dts: Some(dts),
+            duration: 1,
+        }
+    }
+
+    #[test]
+    fn closed_gops_without_b_frames() {
+        let packets = (0..10)
+            .map(|i| packet(i % 4 == 0, i, i))
+            .collect::<Vec<_>>();
+        let gops = GopStructure::from_packets(0, Rational(1, 2), &packets);
+        assert_eq!(3, gops.gop_count);
+        assert_eq!(Some(2), gops.min_frames);
+        assert_eq!(Some(4), gops.max_frames);
+        assert_eq!(Some("3.33".to_string()), gops.average_frames);
+        assert_eq!(Some(1f64), gops._min_duration);
+        assert_eq!(Some(2f64), gops._max_duration);
+        assert_eq!(Some(true), gops.closed_gops);
+        assert!(!gops.b_frames);
+        assert_eq!(
+            vec![0, 4, 8],
+            gops.first_keyframes
+                .iter()
+                .map(|k| k.frame)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn open_gops_with_b_frames() {
+        // Decoding order I0 P3 B1 B2 I6 B4 B5 P9 B7 B8, where B4 and B5
+        // are leading pictures of the second GOP.
+        let packets = [
+            packet(true, 0, -1),
+            packet(false, 3, 0),
+            packet(false, 1, 1),
+            packet(false, 2, 2),
+            packet(true, 6, 3),
+            packet(false, 4, 4),
+            packet(false, 5, 5),
+            packet(false, 9, 6),
+            packet(false, 7, 7),
+            packet(false, 8, 8),
+        ];
+        let gops = GopStructure::from_packets(0, Rational(1, 1), &packets);
+        assert_eq!(2, gops.gop_count);
+        assert_eq!(Some(false), gops.closed_gops);
+        assert!(gops.b_frames);
+    }
+}
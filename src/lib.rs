@@ -1,3 +1,4 @@
+extern crate blake3;
 extern crate ffmpeg_next as ffmpeg;
 #[macro_use]
 extern crate handlebars;
@@ -6,10 +7,12 @@ extern crate lazy_static;
 extern crate libc;
 #[macro_use]
 extern crate log;
+extern crate md5;
 extern crate regex;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
+extern crate sha1;
 extern crate sha2;
 
 #[cfg(test)]
@@ -18,19 +21,52 @@ extern crate quickcheck;
 #[cfg(test)]
 extern crate tempfile;
 
+pub mod bitrate;
+pub mod captions;
+pub mod continuity;
+pub mod crop;
+pub mod decode;
+pub mod detect;
+pub mod edit;
+pub mod filter;
+pub mod gop;
+pub mod loudness;
+pub mod lyrics;
+pub mod manifest;
+pub mod matroska;
 pub mod media_file;
+pub mod mp4;
+pub mod music;
 pub mod prejudice;
+pub mod program;
 pub mod render;
 pub mod scan;
 pub mod stream;
+pub mod streamhash;
+pub mod subtitles;
 pub mod tags;
 pub mod util;
+pub mod verify;
 
+pub use crate::bitrate::BitRateProfile;
+pub use crate::continuity::ContinuityReport;
+pub use crate::crop::CropArea;
+pub use crate::detect::{DetectedInterval, DetectionThresholds};
+pub use crate::edit::TagEdits;
+pub use crate::filter::Predicate;
+pub use crate::gop::GopStructure;
+pub use crate::loudness::Loudness;
+pub use crate::lyrics::Lyrics;
+pub use crate::manifest::{ChecksumEntry, ChecksumStatus};
 pub use crate::media_file::MediaFileMetadata;
+pub use crate::program::ProgramMetadata;
 pub use crate::render::Render;
 pub use crate::scan::ScanType;
 pub use crate::stream::{
     AttachmentMetadata, AudioMetadata, DataMetadata, StreamMetadata, SubtitleMetadata,
     UnknownMetadata, VideoMetadata,
 };
-pub use crate::tags::{Tags, ToTags};
+pub use crate::streamhash::StreamHash;
+pub use crate::tags::{NormalizedTags, TagFamily, TagRules, Tags, ToTags};
+pub use crate::util::ChecksumAlgorithm;
+pub use crate::verify::IntegrityIssue;
@@ -0,0 +1,354 @@
+// Loudness measurement per EBU R128, which builds upon ITU-R BS.1770-4
+// for the loudness algorithm and EBU Tech 3342 for loudness range.
+//
+// https://tech.ebu.ch/docs/r/r128.pdf
+// https://www.itu.int/rec/R-REC-BS.1770
+// https://tech.ebu.ch/docs/tech/tech3342.pdf
+
+use crate::ffmpeg::ffi::{self, AVChannel};
+use crate::ffmpeg::format::context::Input;
+use crate::ffmpeg::media::Type;
+use crate::ffmpeg::util::channel_layout::ChannelLayout;
+use std::collections::{HashMap, VecDeque};
+use std::f64::consts::PI;
+use std::io;
+
+use crate::decode;
+
+const ABSOLUTE_GATE: f64 = -70f64;
+const INTEGRATED_RELATIVE_GATE: f64 = -10f64;
+const RANGE_RELATIVE_GATE: f64 = -20f64;
+
+// Gating blocks are 400ms long for momentary loudness and 3s long for
+// short-term loudness, both advancing in 100ms steps.
+const MOMENTARY_SUBBLOCKS: usize = 4;
+const SHORT_TERM_SUBBLOCKS: usize = 30;
+
+// True peak is measured by 4x oversampling through a 48-tap
+// windowed-sinc interpolation filter, per Annex 2 of BS.1770.
+const OVERSAMPLING: usize = 4;
+const TAPS_PER_PHASE: usize = 12;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Loudness {
+    #[serde(skip_serializing)]
+    pub _integrated: Option<f64>,
+    pub integrated: Option<String>,
+    #[serde(skip_serializing)]
+    pub _range: Option<f64>,
+    pub range: Option<String>,
+    #[serde(skip_serializing)]
+    pub _sample_peak: Option<f64>,
+    pub sample_peak: Option<String>,
+    #[serde(skip_serializing)]
+    pub _true_peak: Option<f64>,
+    pub true_peak: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Biquad {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+    z1: f64,
+    z2: f64,
+}
+
+impl Biquad {
+    fn process(&mut self, x: f64) -> f64 {
+        // Transposed direct form II.
+        let y = self.b0 * x + self.z1;
+        self.z1 = self.b1 * x - self.a1 * y + self.z2;
+        self.z2 = self.b2 * x - self.a2 * y;
+        y
+    }
+}
+
+// The K-weighting pre-filter (a high shelf) and RLB-weighting filter (a
+// high pass), with coefficients derived for arbitrary sample rates the
+// same way as libebur128.
+fn k_weighting_filters(rate: f64) -> (Biquad, Biquad) {
+    let f0 = 1681.974450955533;
+    let g = 3.999843853973347;
+    let q = 0.7071752369554196;
+    let k = (PI * f0 / rate).tan();
+    let vh = 10f64.powf(g / 20f64);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1f64 + k / q + k * k;
+    let shelf = Biquad {
+        b0: (vh + vb * k / q + k * k) / a0,
+        b1: 2f64 * (k * k - vh) / a0,
+        b2: (vh - vb * k / q + k * k) / a0,
+        a1: 2f64 * (k * k - 1f64) / a0,
+        a2: (1f64 - k / q + k * k) / a0,
+        ..Default::default()
+    };
+
+    let f0 = 38.13547087602444;
+    let q = 0.5003270373238773;
+    let k = (PI * f0 / rate).tan();
+    let a0 = 1f64 + k / q + k * k;
+    let high_pass = Biquad {
+        b0: 1f64,
+        b1: -2f64,
+        b2: 1f64,
+        a1: 2f64 * (k * k - 1f64) / a0,
+        a2: (1f64 - k / q + k * k) / a0,
+        ..Default::default()
+    };
+
+    (shelf, high_pass)
+}
+
+fn interpolation_filter() -> Vec<f64> {
+    let taps = OVERSAMPLING * TAPS_PER_PHASE;
+    let center = (taps - 1) as f64 / 2f64;
+    (0..taps)
+        .map(|n| {
+            let t = (n as f64 - center) / OVERSAMPLING as f64;
+            let sinc = if t == 0f64 {
+                1f64
+            } else {
+                (PI * t).sin() / (PI * t)
+            };
+            // Hann window.
+            let window = 0.5 - 0.5 * (2f64 * PI * (n as f64 + 0.5) / taps as f64).cos();
+            sinc * window
+        })
+        .collect()
+}
+
+// Channel weights per BS.1770: LFE channels are excluded, surround
+// channels are weighted by about +1.5dB.
+pub fn channel_weights(layout: &ChannelLayout, channels: usize) -> Vec<f64> {
+    (0..channels)
+        .map(|i| {
+            let channel = unsafe { ffi::av_channel_layout_channel_from_index(&layout.0, i as u32) };
+            match channel {
+                AVChannel::AV_CHAN_LOW_FREQUENCY | AVChannel::AV_CHAN_LOW_FREQUENCY_2 => 0f64,
+                AVChannel::AV_CHAN_BACK_LEFT
+                | AVChannel::AV_CHAN_BACK_RIGHT
+                | AVChannel::AV_CHAN_SIDE_LEFT
+                | AVChannel::AV_CHAN_SIDE_RIGHT => 1.41,
+                _ => 1f64,
+            }
+        })
+        .collect()
+}
+
+fn loudness(power: f64) -> f64 {
+    -0.691 + 10f64 * power.log10()
+}
+
+fn gated_power(powers: &[f64], relative_gate: f64) -> Option<(f64, Vec<f64>)> {
+    let above_absolute = powers
+        .iter()
+        .cloned()
+        .filter(|&p| loudness(p) > ABSOLUTE_GATE)
+        .collect::<Vec<_>>();
+    if above_absolute.is_empty() {
+        return None;
+    }
+    let mean = above_absolute.iter().sum::<f64>() / above_absolute.len() as f64;
+    let threshold = loudness(mean) + relative_gate;
+    let gated = above_absolute
+        .into_iter()
+        .filter(|&p| loudness(p) > threshold)
+        .collect::<Vec<_>>();
+    if gated.is_empty() {
+        return None;
+    }
+    let mean = gated.iter().sum::<f64>() / gated.len() as f64;
+    Some((mean, gated))
+}
+
+pub struct LoudnessMeter {
+    weights: Vec<f64>,
+    filters: Vec<(Biquad, Biquad)>,
+
+    subblock_length: usize,
+    subblock_position: usize,
+    subblock_energy: f64,
+    subblocks: VecDeque<f64>,
+    momentary_powers: Vec<f64>,
+    short_term_powers: Vec<f64>,
+
+    interpolation_filter: Vec<f64>,
+    histories: Vec<VecDeque<f64>>,
+    sample_peak: f64,
+    true_peak: f64,
+}
+
+impl LoudnessMeter {
+    pub fn new(rate: u32, weights: Vec<f64>) -> LoudnessMeter {
+        let channels = weights.len();
+        let filters = k_weighting_filters(rate as f64);
+        LoudnessMeter {
+            weights,
+            filters: vec![filters; channels],
+            subblock_length: ((rate as f64 / 10f64).round() as usize).max(1),
+            subblock_position: 0,
+            subblock_energy: 0f64,
+            subblocks: VecDeque::new(),
+            momentary_powers: Vec::new(),
+            short_term_powers: Vec::new(),
+            interpolation_filter: interpolation_filter(),
+            histories: vec![VecDeque::from(vec![0f64; TAPS_PER_PHASE]); channels],
+            sample_peak: 0f64,
+            true_peak: 0f64,
+        }
+    }
+
+    // Takes one vector of samples per channel, all of the same length.
+    pub fn add_samples(&mut self, samples: &[Vec<f64>]) {
+        let length = samples.iter().map(Vec::len).min().unwrap_or(0);
+        for i in 0..length {
+            for (ch, channel_samples) in samples.iter().enumerate().take(self.weights.len()) {
+                let x = channel_samples[i];
+                self.measure_peaks(ch, x);
+                let (shelf, high_pass) = &mut self.filters[ch];
+                let y = high_pass.process(shelf.process(x));
+                self.subblock_energy += self.weights[ch] * y * y;
+            }
+            self.subblock_position += 1;
+            if self.subblock_position == self.subblock_length {
+                self.end_subblock();
+            }
+        }
+    }
+
+    fn measure_peaks(&mut self, ch: usize, x: f64) {
+        self.sample_peak = self.sample_peak.max(x.abs());
+        let history = &mut self.histories[ch];
+        history.pop_back();
+        history.push_front(x);
+        for phase in 0..OVERSAMPLING {
+            let y = history
+                .iter()
+                .enumerate()
+                .map(|(k, &s)| self.interpolation_filter[k * OVERSAMPLING + phase] * s)
+                .sum::<f64>();
+            self.true_peak = self.true_peak.max(y.abs());
+        }
+    }
+
+    fn end_subblock(&mut self) {
+        self.subblocks.push_back(self.subblock_energy);
+        self.subblock_energy = 0f64;
+        self.subblock_position = 0;
+        if self.subblocks.len() > SHORT_TERM_SUBBLOCKS {
+            self.subblocks.pop_front();
+        }
+        let count = self.subblocks.len();
+        if count >= MOMENTARY_SUBBLOCKS {
+            let energy = self
+                .subblocks
+                .iter()
+                .skip(count - MOMENTARY_SUBBLOCKS)
+                .sum::<f64>();
+            self.momentary_powers
+                .push(energy / (MOMENTARY_SUBBLOCKS * self.subblock_length) as f64);
+        }
+        if count == SHORT_TERM_SUBBLOCKS {
+            let energy = self.subblocks.iter().sum::<f64>();
+            self.short_term_powers
+                .push(energy / (SHORT_TERM_SUBBLOCKS * self.subblock_length) as f64);
+        }
+    }
+
+    pub fn finish(self) -> Loudness {
+        let _integrated = gated_power(&self.momentary_powers, INTEGRATED_RELATIVE_GATE)
+            .map(|(power, _)| loudness(power));
+
+        let _range = gated_power(&self.short_term_powers, RANGE_RELATIVE_GATE).map(|(_, gated)| {
+            let mut levels = gated.into_iter().map(loudness).collect::<Vec<_>>();
+            levels.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            let percentile = |p: f64| levels[((levels.len() - 1) as f64 * p).round() as usize];
+            percentile(0.95) - percentile(0.10)
+        });
+
+        let to_db = |peak: f64| {
+            if peak > 0f64 {
+                Some(20f64 * peak.log10())
+            } else {
+                None
+            }
+        };
+        let _sample_peak = to_db(self.sample_peak);
+        let _true_peak = to_db(self.true_peak.max(self.sample_peak));
+
+        Loudness {
+            _integrated,
+            integrated: _integrated.map(|l| format!("{:.1} LUFS", l)),
+            _range,
+            range: _range.map(|r| format!("{:.1} LU", r)),
+            _sample_peak,
+            sample_peak: _sample_peak.map(|p| format!("{:.1} dBFS", p)),
+            _true_peak,
+            true_peak: _true_peak.map(|p| format!("{:.1} dBTP", p)),
+        }
+    }
+}
+
+// Decodes all audio streams of the input and measures their loudness.
+pub fn measure_loudness(input: &mut Input) -> io::Result<Vec<(usize, Loudness)>> {
+    let indices = input
+        .streams()
+        .filter(|s| s.parameters().medium() == Type::Audio)
+        .map(|s| s.index())
+        .collect::<Vec<_>>();
+
+    let mut meters: HashMap<usize, LoudnessMeter> = HashMap::new();
+    decode::decode_audio(input, &indices, |index, frame| {
+        let meter = meters.entry(index).or_insert_with(|| {
+            let channels = frame.channels() as usize;
+            LoudnessMeter::new(
+                frame.rate(),
+                channel_weights(&frame.channel_layout(), channels),
+            )
+        });
+        meter.add_samples(&decode::audio_frame_samples(frame));
+    })?;
+
+    let mut loudness = meters
+        .into_iter()
+        .map(|(index, meter)| (index, meter.finish()))
+        .collect::<Vec<_>>();
+    loudness.sort_by_key(|(index, _)| *index);
+    Ok(loudness)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A stereo sine wave at -23dBFS is specified to read -23 LUFS in the
+    // EBU Tech 3341 test cases (case 1).
+    #[test]
+    fn stereo_sine_integrated_loudness() {
+        let rate = 48000;
+        let amplitude = 10f64.powf(-23f64 / 20f64);
+        let samples = (0..rate * 20)
+            .map(|i| amplitude * (2f64 * PI * 997f64 * i as f64 / rate as f64).sin())
+            .collect::<Vec<_>>();
+        let mut meter = LoudnessMeter::new(rate as u32, vec![1f64, 1f64]);
+        meter.add_samples(&[samples.clone(), samples]);
+        let loudness = meter.finish();
+        assert!((loudness._integrated.unwrap() + 23f64).abs() < 0.1);
+        assert!(loudness._range.unwrap() < 0.1);
+        assert!((loudness._sample_peak.unwrap() + 23f64).abs() < 0.1);
+        assert!((loudness._true_peak.unwrap() + 23f64).abs() < 0.1);
+    }
+
+    #[test]
+    fn silence_has_no_integrated_loudness() {
+        let mut meter = LoudnessMeter::new(44100, vec![1f64]);
+        meter.add_samples(&[vec![0f64; 44100 * 5]]);
+        let loudness = meter.finish();
+        assert!(loudness._integrated.is_none());
+        assert!(loudness._range.is_none());
+        assert!(loudness._true_peak.is_none());
+    }
+}
@@ -0,0 +1,369 @@
+// Lyrics embedded in music files. Unsynchronized lyrics come from tags
+// (ID3 USLT frames, exposed by libavformat as lyrics or lyrics-<lang>;
+// Vorbis LYRICS or UNSYNCEDLYRICS comments; MP4 ©lyr atoms), possibly in
+// LRC format with timestamps. Synchronized lyrics in ID3 SYLT frames are
+// not exposed by libavformat, so ID3v2 tags are read directly for them.
+
+use regex::Regex;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::tags::Tags;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct LyricsLine {
+    // Seconds since the start.
+    #[serde(skip_serializing)]
+    pub _time: Option<f64>,
+    pub time: Option<String>,
+    pub text: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Lyrics {
+    // The tag key, or "SYLT" for ID3 synchronized lyrics.
+    pub source: String,
+    pub language: Option<String>,
+    pub synced: bool,
+    pub lines: Vec<LyricsLine>,
+}
+
+impl Lyrics {
+    // The text of the lyrics, in LRC format ("[mm:ss.xx]" before each line)
+    // if synced.
+    pub fn text(&self) -> String {
+        self.lines
+            .iter()
+            .map(|line| match &line.time {
+                Some(time) => format!("[{}]{}", time, line.text),
+                None => line.text.clone(),
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+pub fn is_lyrics_key(key: &str) -> bool {
+    lazy_static! {
+        static ref LYRICS_KEY_PATTERN: Regex =
+            Regex::new(r"(?i)^((un)?syncedlyrics|lyrics(-.*)?|\x{a9}lyr|uslt)$").unwrap();
+    }
+    LYRICS_KEY_PATTERN.is_match(key)
+}
+
+// LRC timestamps, e.g., [01:23.45].
+fn format_time(seconds: f64) -> String {
+    let minutes = (seconds / 60f64).floor();
+    format!("{:02}:{:05.2}", minutes, seconds - minutes * 60f64)
+}
+
+fn line(time: Option<f64>, text: &str) -> LyricsLine {
+    LyricsLine {
+        _time: time,
+        time: time.map(format_time),
+        text: text.to_string(),
+    }
+}
+
+// Parses lyrics, possibly in LRC format, where lines may have one or more
+// [mm:ss.xx] timestamps; LRC metadata lines like [ar:Artist] are dropped.
+fn parse_lrc(text: &str) -> (bool, Vec<LyricsLine>) {
+    lazy_static! {
+        static ref TIMESTAMP_PATTERN: Regex =
+            Regex::new(r"^\[(\d+):(\d{1,2}(?:[.:]\d+)?)\]").unwrap();
+        static ref METADATA_PATTERN: Regex = Regex::new(r"^\[[a-z]+:.*\]$").unwrap();
+    }
+    let mut synced = false;
+    let mut lines = Vec::new();
+    for raw_line in text.lines() {
+        let raw_line = raw_line.trim_end();
+        let mut rest = raw_line;
+        let mut times = Vec::new();
+        while let Some(captures) = TIMESTAMP_PATTERN.captures(rest) {
+            let minutes = captures[1].parse::<f64>().unwrap_or(0f64);
+            let seconds = captures[2].replace(':', ".").parse::<f64>().unwrap_or(0f64);
+            times.push(minutes * 60f64 + seconds);
+            rest = &rest[captures[0].len()..];
+        }
+        if times.is_empty() {
+            if METADATA_PATTERN.is_match(raw_line) {
+                continue;
+            }
+            lines.push(line(None, raw_line));
+        } else {
+            synced = true;
+            for time in times {
+                lines.push(line(Some(time), rest));
+            }
+        }
+    }
+    if synced {
+        // Lines repeated with several timestamps are put in order.
+        lines.sort_by(|a, b| {
+            a._time
+                .unwrap_or(0f64)
+                .partial_cmp(&b._time.unwrap_or(0f64))
+                .unwrap()
+        });
+    }
+    (synced, lines)
+}
+
+// The language of USLT lyrics, as in lyrics-eng or lyrics-<desc>-eng.
+fn language_of_key(key: &str) -> Option<String> {
+    let suffix = key.rsplit('-').next()?;
+    if key.contains('-') && suffix.len() == 3 && suffix.chars().all(|c| c.is_ascii_alphabetic()) {
+        Some(suffix.to_ascii_lowercase())
+    } else {
+        None
+    }
+}
+
+pub fn lyrics_from_tags(tags: &Tags) -> Vec<Lyrics> {
+    tags.iter()
+        .filter(|(k, v)| is_lyrics_key(k) && !v.trim().is_empty())
+        .map(|(key, value)| {
+            let (synced, lines) = parse_lrc(value);
+            Lyrics {
+                source: key.clone(),
+                language: language_of_key(key),
+                synced,
+                lines,
+            }
+        })
+        .collect()
+}
+
+fn syncsafe(bytes: &[u8]) -> usize {
+    bytes
+        .iter()
+        .fold(0, |acc, &b| (acc << 7) | (b & 0x7f) as usize)
+}
+
+fn remove_unsynchronization(data: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(data.len());
+    for (i, &b) in data.iter().enumerate() {
+        if !(b == 0 && i > 0 && data[i - 1] == 0xff) {
+            result.push(b);
+        }
+    }
+    result
+}
+
+// Decodes a terminated string in the given ID3 text encoding, returning the
+// string and the rest of the data after the terminator.
+fn decode_string(encoding: u8, data: &[u8]) -> (String, &[u8]) {
+    match encoding {
+        1 | 2 => {
+            let mut end = 0;
+            while end + 1 < data.len() && !(data[end] == 0 && data[end + 1] == 0) {
+                end += 2;
+            }
+            let mut bytes = &data[..end.min(data.len())];
+            let mut big_endian = encoding == 2;
+            if encoding == 1 && bytes.len() >= 2 {
+                big_endian = bytes[..2] == [0xfe, 0xff];
+                if bytes[..2] == [0xfe, 0xff] || bytes[..2] == [0xff, 0xfe] {
+                    bytes = &bytes[2..];
+                }
+            }
+            let units = bytes
+                .chunks_exact(2)
+                .map(|c| {
+                    if big_endian {
+                        u16::from_be_bytes([c[0], c[1]])
+                    } else {
+                        u16::from_le_bytes([c[0], c[1]])
+                    }
+                })
+                .collect::<Vec<_>>();
+            let rest = data.get(end + 2..).unwrap_or(&[]);
+            (String::from_utf16_lossy(&units), rest)
+        }
+        _ => {
+            let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+            let text = if encoding == 3 {
+                String::from_utf8_lossy(&data[..end]).into_owned()
+            } else {
+                // ISO-8859-1
+                data[..end].iter().map(|&b| b as char).collect()
+            };
+            (text, data.get(end + 1..).unwrap_or(&[]))
+        }
+    }
+}
+
+// Parses the body of a SYLT frame: encoding, language, timestamp format,
+// content type and descriptor, followed by terminated strings each
+// followed by a 32-bit timestamp.
+fn parse_sylt(body: &[u8]) -> Option<Lyrics> {
+    if body.len() < 6 {
+        return None;
+    }
+    let encoding = body[0];
+    let language = String::from_utf8_lossy(&body[1..4]).to_ascii_lowercase();
+    // Timestamps are in milliseconds (2) or MPEG frames (1), the latter of
+    // which cannot be converted without decoding.
+    let milliseconds = body[4] == 2;
+    let (_, mut data) = decode_string(encoding, &body[6..]);
+    let mut lines = Vec::new();
+    while !data.is_empty() {
+        let (text, rest) = decode_string(encoding, data);
+        if rest.len() < 4 {
+            break;
+        }
+        let timestamp = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]);
+        data = &rest[4..];
+        // A leading newline marks the start of a new line of lyrics.
+        let time = if milliseconds {
+            Some(timestamp as f64 / 1000f64)
+        } else {
+            None
+        };
+        lines.push(line(time, text.trim_start_matches(['\n', '\r'])));
+    }
+    Some(Lyrics {
+        source: "SYLT".to_string(),
+        language: Some(language).filter(|l| l.chars().all(|c| c.is_ascii_alphabetic())),
+        synced: milliseconds,
+        lines,
+    })
+}
+
+fn read_sylt_frames<R: Read>(reader: &mut R) -> io::Result<Vec<Lyrics>> {
+    let mut header = [0u8; 10];
+    if reader.read_exact(&mut header).is_err() || &header[..3] != b"ID3" {
+        return Ok(Vec::new());
+    }
+    let version = header[3];
+    // ID3v2.2 has no SYLT frame by that name.
+    if version != 3 && version != 4 {
+        return Ok(Vec::new());
+    }
+    let flags = header[5];
+    let size = syncsafe(&header[6..10]);
+    // A tag cut short, e.g., in a partial download, or with a bogus size
+    // is read as far as it goes.
+    let mut tag = Vec::new();
+    reader.take(size as u64).read_to_end(&mut tag)?;
+    if flags & 0x80 != 0 {
+        tag = remove_unsynchronization(&tag);
+    }
+    let mut offset = 0;
+    if flags & 0x40 != 0 && tag.len() >= 4 {
+        // The extended header size excludes itself in v2.3.
+        offset = if version == 3 {
+            4 + u32::from_be_bytes([tag[0], tag[1], tag[2], tag[3]]) as usize
+        } else {
+            syncsafe(&tag[..4])
+        };
+    }
+
+    let mut lyrics = Vec::new();
+    while offset + 10 <= tag.len() && tag[offset] != 0 {
+        let id = &tag[offset..offset + 4];
+        let frame_size = if version == 4 {
+            syncsafe(&tag[offset + 4..offset + 8])
+        } else {
+            u32::from_be_bytes([
+                tag[offset + 4],
+                tag[offset + 5],
+                tag[offset + 6],
+                tag[offset + 7],
+            ]) as usize
+        };
+        let start = offset + 10;
+        let end = (start + frame_size).min(tag.len());
+        if id == b"SYLT" {
+            lyrics.extend(parse_sylt(&tag[start..end]));
+        }
+        offset = end;
+    }
+    Ok(lyrics)
+}
+
+// Synchronized lyrics from the ID3v2 tag at the start of the file, if any.
+pub fn read_synced_lyrics(path: &Path) -> io::Result<Vec<Lyrics>> {
+    read_sylt_frames(&mut File::open(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn lyrics_keys() {
+        assert!(is_lyrics_key("lyrics"));
+        assert!(is_lyrics_key("lyrics-eng"));
+        assert!(is_lyrics_key("LYRICS"));
+        assert!(is_lyrics_key("UNSYNCEDLYRICS"));
+        assert!(is_lyrics_key("\u{a9}lyr"));
+        assert!(!is_lyrics_key("lyricist"));
+        assert_eq!(Some("eng".to_string()), language_of_key("lyrics-eng"));
+        assert_eq!(None, language_of_key("lyrics"));
+    }
+
+    #[test]
+    fn lrc_lyrics() {
+        let tags = vec![(
+            "LYRICS".to_string(),
+            "[ar:Someone]\n[00:12.50]First line\n[00:05.00][01:02.00]Chorus".to_string(),
+        )];
+        let lyrics = lyrics_from_tags(&tags);
+        assert_eq!(1, lyrics.len());
+        assert!(lyrics[0].synced);
+        assert_eq!(
+            "[00:05.00]Chorus\n[00:12.50]First line\n[01:02.00]Chorus",
+            lyrics[0].text()
+        );
+
+        let tags = vec![("lyrics-eng".to_string(), "Plain\nlyrics".to_string())];
+        let lyrics = lyrics_from_tags(&tags);
+        assert!(!lyrics[0].synced);
+        assert_eq!(Some("eng"), lyrics[0].language.as_deref());
+        assert_eq!("Plain\nlyrics", lyrics[0].text());
+    }
+
+    #[test]
+    fn sylt_frame() {
+        let mut body = vec![3u8];
+        body.extend_from_slice(b"eng");
+        body.extend_from_slice(&[2, 1]);
+        body.extend_from_slice(b"desc\0");
+        body.extend_from_slice(b"Hello\0");
+        body.extend_from_slice(&1500u32.to_be_bytes());
+        body.extend_from_slice(b"\nworld\0");
+        body.extend_from_slice(&62250u32.to_be_bytes());
+        let mut frame = b"SYLT".to_vec();
+        frame.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&[0, 0]);
+        frame.extend(body);
+        let mut data = b"ID3\x03\x00\x00".to_vec();
+        let size = frame.len() + 16;
+        data.extend(
+            [21, 14, 7, 0]
+                .iter()
+                .map(|&shift| ((size >> shift) & 0x7f) as u8),
+        );
+        data.extend(frame);
+        data.extend(vec![0u8; 16]);
+
+        let lyrics = read_sylt_frames(&mut Cursor::new(&data)).unwrap();
+        assert_eq!(1, lyrics.len());
+        assert_eq!(Some("eng"), lyrics[0].language.as_deref());
+        assert_eq!("[00:01.50]Hello\n[01:02.25]world", lyrics[0].text());
+
+        // Truncated in the padding, and in the middle of the frame.
+        let truncated = &data[..data.len() - 10];
+        assert_eq!(
+            1,
+            read_sylt_frames(&mut Cursor::new(truncated)).unwrap().len()
+        );
+        let truncated = &data[..16];
+        assert!(read_sylt_frames(&mut Cursor::new(truncated))
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -3,11 +3,18 @@ extern crate metadata;
 extern crate clap;
 extern crate env_logger;
 extern crate ffmpeg_next as ffmpeg;
+#[macro_use]
+extern crate serde_json;
 
-use clap::App;
-use metadata::{MediaFileMetadata, Render};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use metadata::manifest;
+use metadata::{
+    BitRateProfile, ChecksumAlgorithm, ChecksumEntry, ChecksumStatus, DetectionThresholds,
+    MediaFileMetadata, Predicate, Render, TagEdits, TagRules,
+};
+use std::env;
 use std::io;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process;
 
 fn main() {
@@ -22,42 +29,305 @@ fn run_main() -> bool {
         .author("Zhiming Wang <metadata@zhimingwang.org>")
         .about("Media file metadata for human consumption.")
         .args_from_usage(
-            "-c, --checksum     'Include file checksum(s)'
+            "-g, --gop           'Analyze GOP structure of video streams (reads all packets)'
+            -B, --bitrate-profile   'Profile bit rate of each stream over time (reads all packets)'
+            --bitrate-window=[SECONDS]  'Length of bit rate profile windows (default: 1)'
+            --bitrate-series=[FORMAT]   'Print bit rate time series as csv or json instead'
+            -D, --detect        'Detect silence, black and frozen pictures (decodes all streams)'
+            --silence-threshold=[DB]    'Noise level below which audio is silence (default: -60)'
+            --black-threshold=[RATIO]   'Luma level at or below which pixels are black (default: 0.1)'
+            --freeze-threshold=[DB]     'Noise level below which pictures are frozen (default: -60)'
+            --min-duration=[SECONDS]    'Minimum duration of detected intervals (default: 2)'
+            --captions          'Detect closed captions embedded in video (decodes sampled frames)'
+            --continuity        'Check timestamp continuity, PCRs and TS continuity counters'
+            -C, --crop          'Detect letterboxing and pillarboxing (decodes sampled frames)'
+            -L, --loudness      'Measure EBU R128 loudness of audio streams (decodes all audio)'
+            --subtitles         'Count subtitle events and guess text encodings (reads all packets)'
+            --verify            'Decode all streams to check integrity; exit non-zero on damage'
+            --stream-hashes     'Hash packet payloads of each stream (reads all packets)'
+            --frame-hashes      'Also hash decoded audio and video frames (decodes all streams)'
+            --verify-checksums  'Verify files against checksum sidecars or manifests instead'
+            -v, --verbose       'Show more details, e.g., codec tags (fourcc) of streams'
             -t, --tags          'Print metadata tags, except mundane ones'
+            --extract-lyrics    'Print embedded lyrics (LRC format if synced) instead'
             -A, --all-tags      'Print all metadata tags'
+            --truncate=[N]      'Truncate tag values longer than N characters'
+            --width=[COLUMNS]   'Wrap tag values to COLUMNS (default: terminal width; 0 to disable)'
+            --tag-rules=[FILE]  'Read tag hiding rules from FILE (default: ~/.config/metadata/tag_rules)'
             <FILE>...           'Media file(s)'",
         )
+        // -c is a separate flag, since a short option with an optional
+        // value would swallow the rest of a cluster like -ct.
+        .arg(
+            Arg::with_name("checksum-sha256")
+                .short("c")
+                .help("Include SHA-256 file checksum (same as a bare --checksum)"),
+        )
+        .arg(
+            Arg::with_name("checksum")
+                .long("checksum")
+                .value_name("ALGORITHMS")
+                .min_values(0)
+                .require_equals(true)
+                .use_delimiter(true)
+                .help("Include file checksums: md5, sha1, sha256 (default), blake3"),
+        )
+        .arg(repeatable_option(
+            "hide-tag",
+            "REGEX",
+            "Hide tags with keys matching REGEX with -t",
+        ))
+        .arg(repeatable_option(
+            "show-tag",
+            "REGEX",
+            "Show tags with keys matching REGEX with -t",
+        ))
+        .arg(repeatable_option(
+            "where",
+            "PREDICATE",
+            "Only print files matching PREDICATE, e.g., 'height>=1080' or 'tag:artist~=Beatles'",
+        ))
+        .setting(AppSettings::SubcommandsNegateReqs)
+        // Files named like a subcommand can be inspected after other files
+        // or as, e.g., ./set.
+        .setting(AppSettings::ArgsNegateSubcommands)
+        .subcommand(
+            SubCommand::with_name("set")
+                .about("Write a copy of a media file with edited tags, without re-encoding")
+                .args_from_usage(
+                    "-f, --force        'Overwrite OUTPUT if it exists'
+                    <INPUT>             'Input media file'
+                    <OUTPUT>            'Output media file (format guessed from the extension)'",
+                )
+                .arg(repeatable_option(
+                    "tag",
+                    "KEY=VALUE",
+                    "Set a container tag; an empty VALUE removes it",
+                ))
+                .arg(repeatable_option(
+                    "stream-tag",
+                    "INDEX:KEY=VALUE",
+                    "Set a tag of stream #INDEX; an empty VALUE removes it",
+                ))
+                .arg(repeatable_option(
+                    "language",
+                    "INDEX:LANG",
+                    "Set the language of stream #INDEX, e.g., 0:eng",
+                ))
+                .arg(repeatable_option(
+                    "disposition",
+                    "INDEX:[+|-]NAME",
+                    "Set (+) or clear (-) a disposition of stream #INDEX, e.g., 1:+forced",
+                )),
+        )
         .get_matches();
+    if let Some(set_matches) = matches.subcommand_matches("set") {
+        return run_set(set_matches);
+    }
     let files = matches.values_of("FILE").unwrap();
-    let include_checksum = matches.is_present("checksum");
+    // -c and a bare --checksum mean SHA-256.
+    let checksum_algorithms = match matches
+        .values_of("checksum")
+        .map_or(Ok(Vec::new()), |values| {
+            values.map(str::parse::<ChecksumAlgorithm>).collect()
+        }) {
+        Ok(mut algorithms) => {
+            if matches.is_present("checksum-sha256")
+                || (algorithms.is_empty() && matches.is_present("checksum"))
+            {
+                algorithms.push(ChecksumAlgorithm::Sha256);
+            }
+            algorithms.sort();
+            algorithms.dedup();
+            algorithms
+        }
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            return false;
+        }
+    };
+    let include_gop_analysis = matches.is_present("gop");
+    let bit_rate_series_format = matches.value_of("bitrate-series");
+    let include_bit_rate_profile =
+        matches.is_present("bitrate-profile") || bit_rate_series_format.is_some();
+    let bit_rate_window = match parse_f64_option(&matches, "bitrate-window") {
+        Ok(Some(w)) if w >= metadata::bitrate::MIN_WINDOW => w,
+        Ok(Some(w)) => {
+            eprintln!(
+                "Error: invalid value \"{}\" for --bitrate-window (minimum {})",
+                w,
+                metadata::bitrate::MIN_WINDOW
+            );
+            return false;
+        }
+        Ok(None) => metadata::bitrate::DEFAULT_WINDOW,
+        Err(()) => return false,
+    };
+    match bit_rate_series_format {
+        None | Some("csv") | Some("json") => (),
+        Some(f) => {
+            eprintln!("Error: unsupported bit rate series format \"{}\"", f);
+            return false;
+        }
+    }
+    let include_interval_detection = matches.is_present("detect");
+    let mut detection_thresholds = DetectionThresholds::default();
+    match (
+        // Levels are in dB(FS), at most 0.
+        parse_f64_option_in_range(&matches, "silence-threshold", f64::MIN, 0f64),
+        parse_f64_option_in_range(&matches, "black-threshold", 0f64, 1f64),
+        parse_f64_option_in_range(&matches, "freeze-threshold", f64::MIN, 0f64),
+        parse_f64_option_in_range(&matches, "min-duration", 0f64, f64::MAX),
+    ) {
+        (Ok(silence), Ok(black), Ok(freeze), Ok(duration)) => {
+            let t = &mut detection_thresholds;
+            t.silence_noise = silence.unwrap_or(t.silence_noise);
+            t.black_pixel = black.unwrap_or(t.black_pixel);
+            t.freeze_noise = freeze.unwrap_or(t.freeze_noise);
+            if let Some(duration) = duration {
+                t.silence_duration = duration;
+                t.black_duration = duration;
+                t.freeze_duration = duration;
+            }
+        }
+        _ => return false,
+    }
+    let include_caption_detection = matches.is_present("captions");
+    let include_continuity_analysis = matches.is_present("continuity");
+    let include_crop_detection = matches.is_present("crop");
+    let include_loudness = matches.is_present("loudness");
+    let include_subtitle_analysis = matches.is_present("subtitles");
+    let include_integrity_check = matches.is_present("verify");
+    let include_frame_hashes = matches.is_present("frame-hashes");
+    let include_stream_hashes = matches.is_present("stream-hashes") || include_frame_hashes;
+    let verbose = matches.is_present("verbose");
+    let extract_lyrics = matches.is_present("extract-lyrics");
     let include_tags = matches.is_present("tags");
     let include_all_tags = matches.is_present("all-tags");
+    let tag_value_max_length = match parse_usize_option(&matches, "truncate") {
+        Ok(n) => n,
+        Err(()) => return false,
+    };
+    let tag_value_width = match parse_usize_option(&matches, "width") {
+        Ok(Some(0)) => None,
+        Ok(Some(width)) => Some(width),
+        Ok(None) => terminal_width(),
+        Err(()) => return false,
+    };
+    let predicates = match matches.values_of("where").map_or(Ok(Vec::new()), |values| {
+        values.map(str::parse::<Predicate>).collect()
+    }) {
+        Ok(predicates) => predicates,
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            return false;
+        }
+    };
+    // Rules only affect printed tags, so a broken config file is no reason
+    // to fail otherwise.
+    let tag_rules = if include_tags || include_all_tags {
+        match load_tag_rules(&matches) {
+            Ok(rules) => rules,
+            Err(error) => {
+                eprintln!("Error: {}", error);
+                return false;
+            }
+        }
+    } else {
+        TagRules::new()
+    };
+
+    if matches.is_present("verify-checksums") {
+        return run_verify_checksums(files);
+    }
 
     let mut successful = true;
 
-    if ffmpeg::init().is_err() {
-        eprintln!("Error: failed to initialize libav*");
+    if !init_ffmpeg() {
         return false;
     }
-    unsafe {
-        ffmpeg::ffi::av_log_set_level(ffmpeg::ffi::AV_LOG_FATAL);
-    }
 
-    let build_media_file_metadata = |file: &str| -> io::Result<MediaFileMetadata> {
+    // None for files not matching --where predicates. All fields they can
+    // refer to are known once the headers are read, so such files are
+    // skipped before any analysis reads or decodes packets.
+    let build_media_file_metadata = |file: &str| -> io::Result<Option<MediaFileMetadata>> {
         let mut meta = MediaFileMetadata::new(&file)?;
-        meta.include_checksum(include_checksum)?
+        if !predicates.iter().all(|p| p.matches(&meta)) {
+            return Ok(None);
+        }
+        meta.include_checksums(&checksum_algorithms)?
+            .include_gop_analysis(include_gop_analysis)?
+            .include_bit_rate_profile(include_bit_rate_profile, bit_rate_window)?
+            .include_interval_detection(include_interval_detection, &detection_thresholds)?
+            .include_caption_detection(include_caption_detection)?
+            .include_continuity_analysis(include_continuity_analysis)?
+            .include_crop_detection(include_crop_detection)?
+            .include_loudness(include_loudness)?
+            .include_subtitle_analysis(include_subtitle_analysis)?
+            .include_integrity_check(include_integrity_check)?
+            .include_stream_hashes(include_stream_hashes, include_frame_hashes)?
+            .verbose(verbose)
+            .filter_tags(|key| tag_rules.is_boring(key))
+            .wrap_tag_values(tag_value_width)
+            .truncate_tag_values(tag_value_max_length)
             .include_tags(include_tags)
             .include_all_tags(include_all_tags);
-        Ok(meta)
+        Ok(Some(meta))
     };
 
+    if bit_rate_series_format == Some("csv") {
+        println!("{}", BitRateProfile::series_csv_header());
+    }
+
+    let multiple_files = files.len() > 1;
     for file in files {
         if !Path::new(file).is_file() {
             eprintln!("Error: \"{}\" does not exist or is not a file", file);
             successful = false;
             continue;
         }
-        match build_media_file_metadata(&file) {
+        // Files not matching --where predicates are skipped silently.
+        let result = match build_media_file_metadata(&file) {
+            Ok(None) => continue,
+            Ok(Some(m)) => Ok(m),
+            Err(error) => Err(error),
+        };
+        // Damage found by --verify is reported in the output, but also
+        // reflected in the exit status.
+        if result.as_ref().is_ok_and(|m| m.is_damaged()) {
+            successful = false;
+        }
+        match result {
+            Ok(m) if bit_rate_series_format == Some("csv") => {
+                for profile in &m.bit_rate_profiles {
+                    for row in profile.series_csv_rows(&m.path) {
+                        println!("{}", row);
+                    }
+                }
+            }
+            // One JSON document per line per file.
+            Ok(m) if bit_rate_series_format == Some("json") => println!(
+                "{}",
+                json!({"path": m.path, "bit_rate_profiles": m.bit_rate_profiles})
+            ),
+            Ok(m) if extract_lyrics => match m.lyrics() {
+                Ok(lyrics) if lyrics.is_empty() => {
+                    eprintln!("Error: no lyrics in \"{}\"", file);
+                    successful = false;
+                }
+                Ok(lyrics) => {
+                    // Headers like head(1)'s tell files apart.
+                    if multiple_files {
+                        println!("==> {} <==", file);
+                    }
+                    let texts = lyrics.iter().map(|l| l.text()).collect::<Vec<_>>();
+                    println!("{}", texts.join("\n\n"));
+                }
+                Err(error) => {
+                    eprintln!("Error: {}", error);
+                    successful = false;
+                }
+            },
             Ok(m) => match m.render_default() {
                 Ok(rendered) => println!("{}", rendered),
                 Err(_) => {
@@ -74,3 +344,326 @@ fn run_main() -> bool {
 
     successful
 }
+
+// --verify-checksums: each file is either a checksum file (a manifest like
+// SHA256SUMS, or a sidecar like movie.mkv.sha256), whose entries are all
+// verified, or a file to verify against its sidecars.
+fn run_verify_checksums(files: clap::Values) -> bool {
+    let mut successful = true;
+    for file in files {
+        let path = Path::new(file);
+        let entries = if manifest::is_checksum_file(path) {
+            manifest::read_manifest(path)
+        } else if path.is_file() {
+            manifest::read_sidecars(path)
+        } else {
+            eprintln!("Error: \"{}\" does not exist or is not a file", file);
+            successful = false;
+            continue;
+        };
+        let entries = match entries {
+            Ok(entries) if entries.is_empty() => {
+                eprintln!("Error: no checksums found for \"{}\"", file);
+                successful = false;
+                continue;
+            }
+            Ok(entries) => entries,
+            Err(error) => {
+                eprintln!("Error: \"{}\": {}", file, error);
+                successful = false;
+                continue;
+            }
+        };
+
+        // Entries for the same file are verified together, in order of
+        // first appearance.
+        let mut groups: Vec<(PathBuf, Vec<ChecksumEntry>)> = Vec::new();
+        for entry in entries {
+            // Entries that cannot be verified do not stop the others.
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(error) => {
+                    eprintln!("Error: \"{}\": {}", file, error);
+                    successful = false;
+                    continue;
+                }
+            };
+            let entry_path = match &entry.path {
+                Some(p) => p.clone(),
+                None => {
+                    eprintln!("Error: checksum without a file name in \"{}\"", file);
+                    successful = false;
+                    continue;
+                }
+            };
+            match groups.iter_mut().find(|(p, _)| *p == entry_path) {
+                Some((_, group)) => group.push(entry),
+                None => groups.push((entry_path, vec![entry])),
+            }
+        }
+        for (entry_path, group) in groups {
+            match manifest::verify_file(&entry_path, &group) {
+                Ok(status) => {
+                    println!("{}: {}", entry_path.display(), status.name());
+                    if status != ChecksumStatus::Ok {
+                        successful = false;
+                    }
+                }
+                Err(error) => {
+                    eprintln!("Error: \"{}\": {}", entry_path.display(), error);
+                    successful = false;
+                }
+            }
+        }
+    }
+    successful
+}
+
+fn init_ffmpeg() -> bool {
+    if ffmpeg::init().is_err() {
+        eprintln!("Error: failed to initialize libav*");
+        return false;
+    }
+    unsafe {
+        ffmpeg::ffi::av_log_set_level(ffmpeg::ffi::AV_LOG_FATAL);
+    }
+    true
+}
+
+// A long option that may be given multiple times, with exactly one value
+// each time, so that it does not swallow the files that follow.
+fn repeatable_option<'a>(name: &'a str, value_name: &'a str, help: &'a str) -> Arg<'a, 'a> {
+    Arg::with_name(name)
+        .long(name)
+        .value_name(value_name)
+        .multiple(true)
+        .number_of_values(1)
+        .help(help)
+}
+
+// metadata set: remuxes INPUT to OUTPUT with the requested edits, then
+// prints the metadata of OUTPUT.
+fn run_set(matches: &ArgMatches) -> bool {
+    let input = Path::new(matches.value_of("INPUT").unwrap());
+    let output = Path::new(matches.value_of("OUTPUT").unwrap());
+    if !input.is_file() {
+        eprintln!(
+            "Error: \"{}\" does not exist or is not a file",
+            input.display()
+        );
+        return false;
+    }
+    if output.exists() && !matches.is_present("force") {
+        eprintln!(
+            "Error: \"{}\" already exists (use --force to overwrite)",
+            output.display()
+        );
+        return false;
+    }
+    let edits = match parse_tag_edits(matches) {
+        Ok(edits) if edits.is_empty() => {
+            eprintln!("Error: nothing to change");
+            return false;
+        }
+        Ok(edits) => edits,
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            return false;
+        }
+    };
+    if !init_ffmpeg() {
+        return false;
+    }
+    match metadata::edit::write_tags(input, output, &edits) {
+        Ok(mut m) => match m
+            .wrap_tag_values(terminal_width())
+            .include_tags(true)
+            .render_default()
+        {
+            Ok(rendered) => {
+                println!("{}", rendered);
+                true
+            }
+            Err(_) => {
+                eprintln!(
+                    "Error: failed to render metadata for \"{}\"",
+                    output.display()
+                );
+                false
+            }
+        },
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            false
+        }
+    }
+}
+
+// Splits "INDEX:REST" into the stream index and the rest.
+fn split_stream_index(value: &str) -> io::Result<(usize, &str)> {
+    value
+        .split_once(':')
+        .and_then(|(index, rest)| Some((index.parse::<usize>().ok()?, rest)))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("expected INDEX:..., got \"{}\"", value),
+            )
+        })
+}
+
+// Splits "KEY=VALUE"; an empty value means removal.
+fn split_tag(value: &str) -> io::Result<(&str, Option<&str>)> {
+    match value.split_once('=') {
+        Some((key, _)) if key.is_empty() => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("empty key in \"{}\"", value),
+        )),
+        Some((key, "")) => Ok((key, None)),
+        Some((key, v)) => Ok((key, Some(v))),
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("expected KEY=VALUE, got \"{}\"", value),
+        )),
+    }
+}
+
+// Edits in command line order, so that later ones take precedence.
+fn parse_tag_edits(matches: &ArgMatches) -> io::Result<TagEdits> {
+    let mut options = Vec::new();
+    for name in ["tag", "stream-tag", "language", "disposition"] {
+        if let (Some(indices), Some(values)) = (matches.indices_of(name), matches.values_of(name)) {
+            options.extend(indices.zip(values).map(|(i, v)| (i, name, v)));
+        }
+    }
+    options.sort_by_key(|&(i, _, _)| i);
+
+    let mut edits = TagEdits::new();
+    for (_, name, value) in options {
+        match name {
+            "tag" | "stream-tag" => {
+                let (stream, tag) = if name == "tag" {
+                    (None, value)
+                } else {
+                    let (index, tag) = split_stream_index(value)?;
+                    (Some(index), tag)
+                };
+                match split_tag(tag)? {
+                    (key, Some(v)) => edits.set_tag(stream, key, v),
+                    (key, None) => edits.remove_tag(stream, key),
+                };
+            }
+            "language" => {
+                let (index, language) = split_stream_index(value)?;
+                edits.set_language(index, language);
+            }
+            _ => {
+                let (index, disposition) = split_stream_index(value)?;
+                match disposition.strip_prefix('-') {
+                    Some(name) => edits.set_disposition(index, name, false)?,
+                    None => edits.set_disposition(
+                        index,
+                        disposition.strip_prefix('+').unwrap_or(disposition),
+                        true,
+                    )?,
+                };
+            }
+        }
+    }
+    Ok(edits)
+}
+
+// Tag rules from the config file, followed by those on the command line, so
+// that the latter take precedence. The default config file is optional.
+fn load_tag_rules(matches: &ArgMatches) -> io::Result<TagRules> {
+    let mut rules = TagRules::new();
+    let path = match matches.value_of("tag-rules") {
+        Some(path) => Some(PathBuf::from(path)),
+        None => env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+            .map(|dir| dir.join("metadata").join("tag_rules"))
+            .filter(|path| path.is_file()),
+    };
+    if let Some(path) = path {
+        rules.load(&path).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!(
+                    "failed to load tag rules from \"{}\": {}",
+                    path.display(),
+                    e
+                ),
+            )
+        })?;
+    }
+    // Occurrences of --hide-tag and --show-tag are applied in command line
+    // order.
+    let mut cli_rules = Vec::new();
+    for (name, hide) in [("hide-tag", true), ("show-tag", false)] {
+        if let (Some(indices), Some(values)) = (matches.indices_of(name), matches.values_of(name)) {
+            cli_rules.extend(indices.zip(values).map(|(i, v)| (i, v, hide)));
+        }
+    }
+    cli_rules.sort_by_key(|&(i, _, _)| i);
+    for (_, pattern, hide) in cli_rules {
+        if hide {
+            rules.hide(pattern)?;
+        } else {
+            rules.show(pattern)?;
+        }
+    }
+    Ok(rules)
+}
+
+// Width of the terminal if stdout is one.
+fn terminal_width() -> Option<usize> {
+    unsafe {
+        if libc::isatty(libc::STDOUT_FILENO) == 0 {
+            return None;
+        }
+        let mut size: libc::winsize = std::mem::zeroed();
+        if libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) == 0 && size.ws_col > 0 {
+            Some(size.ws_col as usize)
+        } else {
+            None
+        }
+    }
+}
+
+// Parses the value of an optional numeric option. An error message is
+// printed for an invalid value.
+fn parse_f64_option(matches: &ArgMatches, name: &str) -> Result<Option<f64>, ()> {
+    match matches.value_of(name) {
+        Some(s) => s.parse::<f64>().map(Some).map_err(|_| {
+            eprintln!("Error: invalid value \"{}\" for --{}", s, name);
+        }),
+        None => Ok(None),
+    }
+}
+
+// Same, but also rejects values outside [min, max].
+fn parse_f64_option_in_range(
+    matches: &ArgMatches,
+    name: &str,
+    min: f64,
+    max: f64,
+) -> Result<Option<f64>, ()> {
+    match parse_f64_option(matches, name)? {
+        Some(v) if !(min..=max).contains(&v) => {
+            eprintln!("Error: value \"{}\" for --{} out of range", v, name);
+            Err(())
+        }
+        value => Ok(value),
+    }
+}
+
+// Same for non-negative integers.
+fn parse_usize_option(matches: &ArgMatches, name: &str) -> Result<Option<usize>, ()> {
+    match matches.value_of(name) {
+        Some(s) => s.parse::<usize>().map(Some).map_err(|_| {
+            eprintln!("Error: invalid value \"{}\" for --{}", s, name);
+        }),
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,346 @@
+// Verification of files against checksum sidecars (e.g., movie.mkv.sha256
+// next to movie.mkv) and manifests (e.g., SHA256SUMS), in the formats
+// written by sha256sum and friends ("HEX  NAME", or "HEX *NAME" in binary
+// mode) and by BSD tools and `shasum --tag` ("SHA256 (NAME) = HEX").
+// Sidecars may also hold just the digest.
+
+use regex::Regex;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::util::{self, ChecksumAlgorithm};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChecksumEntry {
+    // None for bare digests in sidecars.
+    pub path: Option<PathBuf>,
+    pub algorithm: ChecksumAlgorithm,
+    // Lowercase hex.
+    pub digest: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChecksumStatus {
+    Ok,
+    Failed,
+    // The file does not exist.
+    Missing,
+}
+
+impl ChecksumStatus {
+    pub fn name(self) -> &'static str {
+        match self {
+            ChecksumStatus::Ok => "OK",
+            ChecksumStatus::Failed => "FAILED",
+            ChecksumStatus::Missing => "MISSING",
+        }
+    }
+}
+
+// Sidecar extensions in order of preference.
+const SIDECAR_EXTENSIONS: [(&str, ChecksumAlgorithm); 5] = [
+    ("sha256", ChecksumAlgorithm::Sha256),
+    ("b3", ChecksumAlgorithm::Blake3),
+    ("blake3", ChecksumAlgorithm::Blake3),
+    ("sha1", ChecksumAlgorithm::Sha1),
+    ("md5", ChecksumAlgorithm::Md5),
+];
+
+// Guesses the algorithm from the name of a checksum file, e.g.,
+// movie.mkv.md5 or SHA256SUMS.
+fn algorithm_of_file_name(path: &Path) -> Option<ChecksumAlgorithm> {
+    lazy_static! {
+        static ref ALGORITHM_PATTERN: Regex =
+            Regex::new(r"(?i)(?:^|[._-])(md5|sha-?1|sha-?256|blake3|b3)(sums?)?$").unwrap();
+    }
+    let stem = path.file_name()?.to_str()?;
+    let captures = ALGORITHM_PATTERN.captures(stem)?;
+    captures[1].parse().ok()
+}
+
+pub fn is_checksum_file(path: &Path) -> bool {
+    algorithm_of_file_name(path).is_some()
+}
+
+// Without a hint, the algorithm is guessed from the length of the digest;
+// 64 hex digits are taken for SHA-256 rather than BLAKE3.
+fn algorithm_of_digest(digest: &str) -> Option<ChecksumAlgorithm> {
+    match digest.len() {
+        32 => Some(ChecksumAlgorithm::Md5),
+        40 => Some(ChecksumAlgorithm::Sha1),
+        64 => Some(ChecksumAlgorithm::Sha256),
+        _ => None,
+    }
+}
+
+// Undoes the escaping of names with backslashes or newlines by sha256sum,
+// which is marked by a leading backslash on the line.
+fn unescape_name(name: &str) -> String {
+    let mut result = String::new();
+    let mut chars = name.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') => result.push('\n'),
+                Some('r') => result.push('\r'),
+                Some(c) => result.push(c),
+                None => result.push('\\'),
+            }
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+// Parses the content of a checksum file. Relative names are resolved
+// against base_dir; algorithm is a hint from the file name. Malformed lines
+// fail the whole file, which is likely not a checksum file at all, but
+// entries with unsupported or unknown algorithms are errors of their own,
+// so that the other entries can still be verified.
+pub fn parse_manifest(
+    content: &str,
+    algorithm: Option<ChecksumAlgorithm>,
+    base_dir: &Path,
+) -> io::Result<Vec<io::Result<ChecksumEntry>>> {
+    lazy_static! {
+        static ref GNU_PATTERN: Regex =
+            Regex::new(r"^(\\)?([0-9a-fA-F]+)(?:[ \t][ *]?(.+))?$").unwrap();
+        static ref BSD_PATTERN: Regex =
+            Regex::new(r"^(\\)?([A-Za-z0-9-]+) ?\((.+)\) ?= ?([0-9a-fA-F]+)$").unwrap();
+    }
+    let mut entries = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (escaped, name, digest, algorithm) = if let Some(c) = BSD_PATTERN.captures(line) {
+            let algorithm = match c[2].parse::<ChecksumAlgorithm>() {
+                Ok(algorithm) => algorithm,
+                Err(e) => {
+                    entries.push(Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{} on line {}", e, i + 1),
+                    )));
+                    continue;
+                }
+            };
+            (
+                c.get(1).is_some(),
+                Some(c[3].to_string()),
+                c[4].to_string(),
+                Some(algorithm),
+            )
+        } else if let Some(c) = GNU_PATTERN.captures(line) {
+            let digest = c[2].to_string();
+            let algorithm = algorithm.or_else(|| algorithm_of_digest(&digest));
+            (
+                c.get(1).is_some(),
+                c.get(3).map(|m| m.as_str().to_string()),
+                digest,
+                algorithm,
+            )
+        } else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("malformed checksum line {}: \"{}\"", i + 1, line),
+            ));
+        };
+        let algorithm = match algorithm {
+            Some(algorithm) => algorithm,
+            None => {
+                entries.push(Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unknown checksum algorithm on line {}", i + 1),
+                )));
+                continue;
+            }
+        };
+        let name = name.map(|n| if escaped { unescape_name(&n) } else { n });
+        entries.push(Ok(ChecksumEntry {
+            path: name.map(|n| base_dir.join(n)),
+            algorithm,
+            digest: digest.to_ascii_lowercase(),
+        }));
+    }
+    Ok(entries)
+}
+
+// Bare digests in a sidecar given as a manifest, e.g., movie.mkv.sha256,
+// are for the file named by dropping the extension.
+pub fn read_manifest(path: &Path) -> io::Result<Vec<io::Result<ChecksumEntry>>> {
+    let content = fs::read_to_string(path)?;
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut entries = parse_manifest(&content, algorithm_of_file_name(path), base_dir)?;
+    if path.extension().is_some() {
+        for entry in entries.iter_mut().flatten().filter(|e| e.path.is_none()) {
+            entry.path = Some(path.with_extension(""));
+        }
+    }
+    Ok(entries)
+}
+
+// Checksums of a file from its sidecars, i.e., FILE.sha256, FILE.md5,
+// etc. Entries naming other files are ignored, unless a sidecar has only
+// one entry (names may be written as full paths, or be stale).
+pub fn read_sidecars(path: &Path) -> io::Result<Vec<ChecksumEntry>> {
+    let file_name = path.file_name().unwrap_or_default();
+    let mut entries = Vec::new();
+    for (extension, algorithm) in SIDECAR_EXTENSIONS.iter() {
+        let mut sidecar = path.as_os_str().to_owned();
+        sidecar.push(".");
+        sidecar.push(extension);
+        let sidecar = PathBuf::from(sidecar);
+        if !sidecar.is_file() {
+            continue;
+        }
+        let content = fs::read_to_string(&sidecar)?;
+        let sidecar_entries = parse_manifest(&content, Some(*algorithm), Path::new(""))?
+            .into_iter()
+            .collect::<io::Result<Vec<_>>>()?;
+        let single = sidecar_entries.len() == 1;
+        entries.extend(
+            sidecar_entries
+                .into_iter()
+                .filter(|e| {
+                    single
+                        || e.path
+                            .as_ref()
+                            .is_none_or(|p| p.file_name() == Some(file_name))
+                })
+                .map(|e| ChecksumEntry {
+                    path: Some(path.to_path_buf()),
+                    ..e
+                }),
+        );
+    }
+    Ok(entries)
+}
+
+// Verifies a file against the expected digests, all computed in a single
+// pass. The file fails if any digest mismatches.
+pub fn verify_file(path: &Path, expected: &[ChecksumEntry]) -> io::Result<ChecksumStatus> {
+    if !path.exists() {
+        return Ok(ChecksumStatus::Missing);
+    }
+    let mut algorithms = expected.iter().map(|e| e.algorithm).collect::<Vec<_>>();
+    algorithms.sort();
+    algorithms.dedup();
+    let actual = util::checksums(path, &algorithms)?;
+    if expected.iter().all(|e| actual[&e.algorithm] == e.digest) {
+        Ok(ChecksumStatus::Ok)
+    } else {
+        Ok(ChecksumStatus::Failed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    const ABC_SHA256: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+    const ABC_MD5: &str = "900150983cd24fb0d6963f7d28e17f72";
+
+    #[test]
+    fn parse_formats() {
+        let content = format!(
+            "# comment\n{}  a.mkv\n{} *dir/b c.mp4\nMD5 (d.flac) = {}\n\\{}  e\\\\f\n",
+            ABC_SHA256,
+            ABC_SHA256,
+            ABC_MD5.to_uppercase(),
+            ABC_SHA256
+        );
+        let entries = parse_manifest(&content, None, Path::new("/base"))
+            .unwrap()
+            .into_iter()
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+        let paths = entries
+            .iter()
+            .map(|e| e.path.clone().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                PathBuf::from("/base/a.mkv"),
+                PathBuf::from("/base/dir/b c.mp4"),
+                PathBuf::from("/base/d.flac"),
+                PathBuf::from("/base/e\\f"),
+            ],
+            paths
+        );
+        assert_eq!(ChecksumAlgorithm::Sha256, entries[0].algorithm);
+        assert_eq!(ChecksumAlgorithm::Md5, entries[2].algorithm);
+        assert_eq!(ABC_MD5, entries[2].digest);
+
+        let entries =
+            parse_manifest(ABC_SHA256, Some(ChecksumAlgorithm::Blake3), Path::new("")).unwrap();
+        let entry = entries[0].as_ref().unwrap();
+        assert_eq!(None, entry.path);
+        assert_eq!(ChecksumAlgorithm::Blake3, entry.algorithm);
+        assert!(parse_manifest("not a checksum", None, Path::new("")).is_err());
+        let entries = parse_manifest("abcd  file", None, Path::new("")).unwrap();
+        assert!(entries[0].is_err());
+    }
+
+    #[test]
+    fn unsupported_algorithm_entries() {
+        let content = format!(
+            "SHA512 (x.mkv) = {}{}\nSHA256 (y.mkv) = {}\n",
+            ABC_SHA256, ABC_SHA256, ABC_SHA256
+        );
+        let entries = parse_manifest(&content, None, Path::new("")).unwrap();
+        assert_eq!(2, entries.len());
+        let error = entries[0].as_ref().unwrap_err().to_string();
+        assert!(error.contains("SHA512"), "{}", error);
+        assert!(error.contains("line 1"), "{}", error);
+        assert_eq!(
+            Some(PathBuf::from("y.mkv")),
+            entries[1].as_ref().unwrap().path
+        );
+    }
+
+    #[test]
+    fn checksum_file_names() {
+        assert!(is_checksum_file(Path::new("SHA256SUMS")));
+        assert!(is_checksum_file(Path::new("movie.mkv.md5")));
+        assert!(is_checksum_file(Path::new("album.b3sum")));
+        assert!(!is_checksum_file(Path::new("movie.mkv")));
+        assert!(!is_checksum_file(Path::new("movie.ab3")));
+    }
+
+    #[test]
+    fn verify_sidecars() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("a.mkv");
+        fs::File::create(&path).unwrap().write_all(b"abc").unwrap();
+        fs::write(
+            dir.path().join("a.mkv.sha256"),
+            format!("{}  a.mkv\n", ABC_SHA256),
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("a.mkv.md5"),
+            "0123456789abcdef0123456789abcdef\n",
+        )
+        .unwrap();
+
+        let entries = read_sidecars(&path).unwrap();
+        assert_eq!(2, entries.len());
+        assert_eq!(
+            ChecksumStatus::Ok,
+            verify_file(&path, &entries[..1]).unwrap()
+        );
+        assert_eq!(
+            ChecksumStatus::Failed,
+            verify_file(&path, &entries).unwrap()
+        );
+        assert_eq!(
+            ChecksumStatus::Missing,
+            verify_file(&dir.path().join("b.mkv"), &entries).unwrap()
+        );
+    }
+}
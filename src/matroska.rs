@@ -0,0 +1,292 @@
+// Segment information of Matroska and WebM files, read directly from the
+// EBML structure since libavformat only exposes part of it (the muxing
+// application as the "encoder" tag, the date as "creation_time").
+
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+const EBML_ID: u32 = 0x1a45_dfa3;
+const SEGMENT_ID: u32 = 0x1853_8067;
+const SEEK_HEAD_ID: u32 = 0x114d_9b74;
+const SEEK_ID: u32 = 0x4dbb;
+const SEEK_ID_ID: u32 = 0x53ab;
+const INFO_ID: u32 = 0x1549_a966;
+const SEGMENT_UID_ID: u32 = 0x73a4;
+const MUXING_APP_ID: u32 = 0x4d80;
+const WRITING_APP_ID: u32 = 0x5741;
+const DATE_UTC_ID: u32 = 0x4461;
+const CUES_ID: u32 = 0x1c53_bb6b;
+
+// Matroska dates are nanoseconds since 2001-01-01T00:00:00 UTC, which is
+// this many seconds after the Unix epoch.
+const MATROSKA_EPOCH: i64 = 978_307_200;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct MatroskaInfo {
+    pub muxing_app: Option<String>,
+    pub writing_app: Option<String>,
+    pub segment_uid: Option<String>,
+    #[serde(skip_serializing)]
+    pub _date: Option<i64>,
+    pub date: Option<String>,
+    // Whether the file has a seek index.
+    pub has_cues: bool,
+}
+
+struct Element {
+    id: u32,
+    data_offset: u64,
+    // None for unknown sizes (live streams).
+    size: Option<u64>,
+}
+
+// Reads a variable length integer, returning its value (with the length
+// marker removed if value is true) and length.
+fn read_vint<R: Read>(reader: &mut R, value: bool) -> io::Result<(u64, u32)> {
+    let mut first = [0u8; 1];
+    reader.read_exact(&mut first)?;
+    let length = first[0].leading_zeros() + 1;
+    if length > 8 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "invalid EBML integer",
+        ));
+    }
+    let mut v = if value {
+        (first[0] as u64) & (0xff >> length)
+    } else {
+        first[0] as u64
+    };
+    for _ in 1..length {
+        let mut byte = [0u8; 1];
+        reader.read_exact(&mut byte)?;
+        v = (v << 8) | byte[0] as u64;
+    }
+    Ok((v, length))
+}
+
+fn read_element_header<R: Read + Seek>(reader: &mut R, offset: u64) -> io::Result<Element> {
+    reader.seek(SeekFrom::Start(offset))?;
+    let (id, id_length) = read_vint(reader, false)?;
+    let (size, size_length) = read_vint(reader, true)?;
+    let unknown = size == (1u64 << (7 * size_length)) - 1;
+    Ok(Element {
+        id: id as u32,
+        data_offset: offset + (id_length + size_length) as u64,
+        size: if unknown { None } else { Some(size) },
+    })
+}
+
+// Reads the child elements between start and end, calling f with each;
+// stops at the first child of unknown size (after calling f with it).
+fn for_each_child<R, F>(reader: &mut R, start: u64, end: u64, mut f: F) -> io::Result<()>
+where
+    R: Read + Seek,
+    F: FnMut(&mut R, &Element) -> io::Result<()>,
+{
+    let mut offset = start;
+    while offset < end {
+        let element = read_element_header(reader, offset)?;
+        f(reader, &element)?;
+        match element.size {
+            Some(size) => offset = element.data_offset + size,
+            None => break,
+        }
+    }
+    Ok(())
+}
+
+fn read_data<R: Read + Seek>(reader: &mut R, element: &Element) -> io::Result<Vec<u8>> {
+    // Nothing of interest is anywhere near this large.
+    let size = element.size.unwrap_or(0).min(4096) as usize;
+    let mut data = vec![0u8; size];
+    reader.seek(SeekFrom::Start(element.data_offset))?;
+    reader.read_exact(&mut data)?;
+    Ok(data)
+}
+
+fn read_string<R: Read + Seek>(reader: &mut R, element: &Element) -> io::Result<String> {
+    let data = read_data(reader, element)?;
+    // Strings may be padded with nulls.
+    let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+    Ok(String::from_utf8_lossy(&data[..end]).into_owned())
+}
+
+// Formats a Unix timestamp as an ISO 8601 date and time in UTC.
+fn format_date(unix_seconds: i64) -> String {
+    let days = unix_seconds.div_euclid(86400);
+    let seconds = unix_seconds.rem_euclid(86400);
+    // Civil from days, after Howard Hinnant's algorithm.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+fn parse<R: Read + Seek>(reader: &mut R, length: u64) -> io::Result<Option<MatroskaInfo>> {
+    let header = read_element_header(reader, 0)?;
+    if header.id != EBML_ID {
+        return Ok(None);
+    }
+    let mut segment = None;
+    for_each_child(reader, 0, length, |_, element| {
+        if element.id == SEGMENT_ID && segment.is_none() {
+            segment = Some((
+                element.data_offset,
+                element.size.map_or(length, |s| element.data_offset + s),
+            ));
+        }
+        Ok(())
+    })?;
+    let (start, end) = match segment {
+        Some((start, end)) => (start, end.min(length)),
+        None => return Ok(None),
+    };
+
+    let mut info = MatroskaInfo {
+        muxing_app: None,
+        writing_app: None,
+        segment_uid: None,
+        _date: None,
+        date: None,
+        has_cues: false,
+    };
+    // Top level elements are walked until the cues are found; clusters
+    // are skipped over by their sizes.
+    let mut offset = start;
+    while offset < end && !(info.has_cues && info.muxing_app.is_some()) {
+        let element = read_element_header(reader, offset)?;
+        let element_end = element.size.map(|s| element.data_offset + s);
+        match element.id {
+            INFO_ID => {
+                for_each_child(
+                    reader,
+                    element.data_offset,
+                    element_end.unwrap_or(end),
+                    |r, e| {
+                        match e.id {
+                            MUXING_APP_ID => info.muxing_app = Some(read_string(r, e)?),
+                            WRITING_APP_ID => info.writing_app = Some(read_string(r, e)?),
+                            SEGMENT_UID_ID => {
+                                let uid = read_data(r, e)?;
+                                info.segment_uid =
+                                    Some(uid.iter().map(|b| format!("{:02x}", b)).collect());
+                            }
+                            DATE_UTC_ID => {
+                                let data = read_data(r, e)?;
+                                if data.len() == 8 {
+                                    let mut bytes = [0u8; 8];
+                                    bytes.copy_from_slice(&data);
+                                    info._date = Some(i64::from_be_bytes(bytes));
+                                }
+                            }
+                            _ => (),
+                        }
+                        Ok(())
+                    },
+                )?;
+            }
+            SEEK_HEAD_ID => {
+                for_each_child(
+                    reader,
+                    element.data_offset,
+                    element_end.unwrap_or(end),
+                    |r, seek| {
+                        if seek.id == SEEK_ID {
+                            let seek_end = seek.data_offset + seek.size.unwrap_or(0);
+                            for_each_child(r, seek.data_offset, seek_end, |r, e| {
+                                if e.id == SEEK_ID_ID && read_data(r, e)? == CUES_ID.to_be_bytes() {
+                                    info.has_cues = true;
+                                }
+                                Ok(())
+                            })?;
+                        }
+                        Ok(())
+                    },
+                )?;
+            }
+            CUES_ID => info.has_cues = true,
+            _ => (),
+        }
+        match element_end {
+            Some(element_end) => offset = element_end,
+            None => break,
+        }
+    }
+    info.date = info
+        ._date
+        .map(|ns| format_date(MATROSKA_EPOCH + ns.div_euclid(1_000_000_000)));
+    Ok(Some(info))
+}
+
+// Returns None if the file does not look like Matroska after all.
+pub fn parse_segment_info(path: &Path) -> io::Result<Option<MatroskaInfo>> {
+    let file = File::open(path)?;
+    let length = file.metadata()?.len();
+    parse(&mut BufReader::new(file), length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn element(id: u32, data: &[u8]) -> Vec<u8> {
+        let mut e = id
+            .to_be_bytes()
+            .iter()
+            .skip_while(|&&b| b == 0)
+            .cloned()
+            .collect::<Vec<_>>();
+        // 8-byte sizes, for simplicity.
+        e.push(0x01);
+        e.extend_from_slice(&(data.len() as u64).to_be_bytes()[1..]);
+        e.extend_from_slice(data);
+        e
+    }
+
+    #[test]
+    fn segment_info() {
+        let mut info = element(MUXING_APP_ID, b"libebml v1.3.0 + libmatroska v1.4.0");
+        info.extend(element(WRITING_APP_ID, b"mkvmerge v8.0.0\0"));
+        info.extend(element(SEGMENT_UID_ID, &[0xab; 16]));
+        info.extend(element(DATE_UTC_ID, &0i64.to_be_bytes()));
+        let mut segment = element(INFO_ID, &info);
+        segment.extend(element(CUES_ID, &[]));
+        let mut data = element(EBML_ID, &[]);
+        data.extend(element(SEGMENT_ID, &segment));
+
+        let info = parse(&mut Cursor::new(&data), data.len() as u64)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            Some("libebml v1.3.0 + libmatroska v1.4.0"),
+            info.muxing_app.as_deref()
+        );
+        assert_eq!(Some("mkvmerge v8.0.0"), info.writing_app.as_deref());
+        assert_eq!(Some("ab".repeat(16)), info.segment_uid);
+        assert_eq!(Some("2001-01-01 00:00:00 UTC"), info.date.as_deref());
+        assert!(info.has_cues);
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!("1970-01-01 00:00:00 UTC", format_date(0));
+        assert_eq!("2020-02-29 12:34:56 UTC", format_date(1_582_979_696));
+    }
+}
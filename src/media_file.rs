@@ -1,22 +1,58 @@
 use crate::ffmpeg;
 use crate::ffmpeg::media::Type;
 use crate::ffmpeg::util::rational::Rational;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::io;
 use std::path::Path;
 
+use crate::bitrate::{self, BitRateProfile};
+use crate::captions;
+use crate::continuity::{self, ContinuityReport};
+use crate::crop::{self, CropArea};
+use crate::detect::{self, DetectedInterval, DetectionThresholds};
+use crate::gop::{self, GopStructure};
+use crate::loudness;
+use crate::lyrics::{self, Lyrics};
+use crate::matroska::{self, MatroskaInfo};
+use crate::mp4::{self, Mp4Layout};
+use crate::music::MusicInfo;
 use crate::prejudice;
+use crate::program::{self, ProgramMetadata};
+use crate::render::Render;
 use crate::scan::{self, ScanType};
+use crate::stream::subtitle::SubtitleKind;
 use crate::stream::{parse_stream_meatadata, StreamMetadata};
-use crate::tags::{Tags, ToTags};
-use crate::util;
+use crate::streamhash;
+use crate::subtitles;
+use crate::tags::{self, NormalizedTags, TagFamily, Tags, ToTags};
+use crate::util::{self, ChecksumAlgorithm};
+use crate::verify::{self, IntegrityIssue};
 
 #[derive(Clone, Debug, Serialize)]
 pub struct MediaFileMetadataOptions {
     pub include_checksum: bool,
     pub include_tags: bool,
     pub include_all_tags: bool,
+    // Whether any of the included analyses decodes frames.
     pub decode_frames: bool,
+    pub include_gop_analysis: bool,
+    pub include_bit_rate_profile: bool,
+    pub bit_rate_window: f64,
+    pub include_loudness: bool,
+    pub include_interval_detection: bool,
+    pub include_crop_detection: bool,
+    pub include_integrity_check: bool,
+    pub include_continuity_analysis: bool,
+    pub include_caption_detection: bool,
+    pub include_subtitle_analysis: bool,
+    pub include_stream_hashes: bool,
+    pub include_frame_hashes: bool,
+    pub verbose: bool,
+    // Tag values are wrapped to this many columns.
+    pub tag_value_width: Option<usize>,
+    // Tag values longer than this many characters are truncated.
+    pub tag_value_max_length: Option<usize>,
 }
 
 #[derive(Clone, Debug, Serialize)]
@@ -35,12 +71,24 @@ pub struct MediaFileMetadata {
     pub file_size: u64,
     pub file_size_base10: String,
     pub file_size_base2: String,
+    // SHA-256 digest, if computed; also in checksums.
     pub hash: Option<String>,
+    // Hex digests by algorithm.
+    pub checksums: BTreeMap<ChecksumAlgorithm, String>,
 
     pub title: Option<String>,
 
     pub container_format: String,
 
+    // Only available for the MP4/QuickTime family.
+    pub mp4_layout: Option<Mp4Layout>,
+    pub fast_start: Option<String>,
+    pub fragmented: Option<String>,
+    // Only available for Matroska and WebM.
+    pub matroska: Option<MatroskaInfo>,
+    // ReplayGain and MusicBrainz tags, if any.
+    pub music: Option<MusicInfo>,
+
     #[serde(skip_serializing)]
     pub _duration: Option<f64>,
     pub duration: Option<String>,
@@ -54,6 +102,10 @@ pub struct MediaFileMetadata {
     #[serde(skip_serializing)]
     pub _display_aspect_ratio: Option<Rational>,
     pub display_aspect_ratio: Option<String>,
+    pub crop_area: Option<CropArea>,
+    #[serde(skip_serializing)]
+    pub _effective_aspect_ratio: Option<Rational>,
+    pub effective_aspect_ratio: Option<String>,
 
     #[serde(skip_serializing)]
     pub _scan_type: Option<ScanType>,
@@ -71,10 +123,32 @@ pub struct MediaFileMetadata {
     pub _streams_metadata: Vec<StreamMetadata>,
     pub streams_metadata_rendered: Vec<String>,
 
+    pub programs: Vec<ProgramMetadata>,
+    pub programs_rendered: Vec<String>,
+
+    pub gop_structures: Vec<GopStructure>,
+    pub gop_structures_rendered: Vec<String>,
+
+    pub bit_rate_profiles: Vec<BitRateProfile>,
+    pub bit_rate_profiles_rendered: Vec<String>,
+
+    pub detected_intervals: Vec<DetectedInterval>,
+    pub detected_intervals_rendered: Vec<String>,
+
+    pub integrity_issues: Vec<IntegrityIssue>,
+    pub integrity_issues_rendered: Vec<String>,
+
+    pub continuity_reports: Vec<ContinuityReport>,
+    pub continuity_reports_rendered: Vec<String>,
+
+    // Content hashes are stored with the streams.
+    pub stream_hashes_rendered: Vec<String>,
+
     pub tags: Tags,
     pub filtered_tags: Tags,
     pub streams_tags: Vec<StreamTags>,
     pub streams_filtered_tags: Vec<StreamTags>,
+    pub normalized_tags: NormalizedTags,
 }
 
 impl MediaFileMetadata {
@@ -87,14 +161,35 @@ impl MediaFileMetadata {
         let file_size_base10 = util::human_size(file_size, util::Base::Base10);
         let file_size_base2 = util::human_size(file_size, util::Base::Base2);
 
-        // let hash = if options.include_checksum {
-        //     Some(util::sha256_hash(path)?)
-        // } else {
-        //     None
-        // };
-
         let container_format = prejudice::format_name(&format_ctx.format(), path);
 
+        let mp4_layout = if format_ctx.format().name() == "mov,mp4,m4a,3gp,3g2,mj2" {
+            mp4::analyze_layout(path)
+                .map_err(|e| debug!("failed to walk MP4 boxes: {}", e))
+                .ok()
+        } else {
+            None
+        };
+        let matroska = if format_ctx.format().name() == "matroska,webm" {
+            matroska::parse_segment_info(path)
+                .map_err(|e| debug!("failed to parse Matroska segment info: {}", e))
+                .ok()
+                .flatten()
+        } else {
+            None
+        };
+        let fast_start = mp4_layout
+            .as_ref()
+            .map(|l| if l.fast_start { "yes" } else { "no" }.to_string());
+        let fragmented = mp4_layout.as_ref().map(|l| {
+            match (l.fragmented, l.cmaf) {
+                (true, true) => "yes (CMAF)",
+                (true, false) => "yes",
+                (false, _) => "no",
+            }
+            .to_string()
+        });
+
         let _duration = if format_ctx.duration() >= 0 {
             Some(format_ctx.duration() as f64 / ffmpeg::ffi::AV_TIME_BASE as f64)
         } else {
@@ -128,14 +223,16 @@ impl MediaFileMetadata {
         for stream in format_ctx.streams() {
             _streams_metadata.push(parse_stream_meatadata(stream)?);
         }
-        let streams_metadata_rendered = _streams_metadata
+        let streams_metadata_rendered = render_streams_metadata(&_streams_metadata);
+
+        let programs = program::parse_programs(&format_ctx);
+        let programs_rendered = programs
             .iter()
-            .map(|m| {
-                m.render_default().unwrap_or_else(|_| {
-                    panic!("failed to render metadata for stream #{}", m.index())
-                })
+            .map(|p| {
+                p.render_default()
+                    .unwrap_or_else(|_| panic!("failed to render program {}", p.number))
             })
-            .collect::<Vec<_>>();
+            .collect();
 
         let best_vstream_index = format_ctx.streams().best(Type::Video).map(|s| s.index());
         let best_vstream_metadata =
@@ -175,7 +272,7 @@ impl MediaFileMetadata {
         let tags = tagdict.to_tags();
         let filtered_tags = tagdict.to_filtered_tags();
 
-        let streams_tags = format_ctx
+        let streams_tags: Vec<StreamTags> = format_ctx
             .streams()
             .map(|s| StreamTags {
                 index: s.index(),
@@ -190,12 +287,36 @@ impl MediaFileMetadata {
             })
             .collect();
 
+        // Ogg keeps comments with the streams rather than the container.
+        let tag_family = TagFamily::from_format_name(format_ctx.format().name());
+        let mut tag_sources = vec![&tags];
+        if tag_family == TagFamily::VorbisComment {
+            tag_sources.extend(streams_tags.iter().map(|s| &s.tags));
+        }
+        let normalized_tags = NormalizedTags::new(&tag_sources, tag_family);
+        let music = MusicInfo::new(&tag_sources);
+
         Ok(MediaFileMetadata {
             options: MediaFileMetadataOptions {
                 include_checksum: false,
                 include_tags: false,
                 include_all_tags: false,
                 decode_frames: false,
+                include_gop_analysis: false,
+                include_bit_rate_profile: false,
+                bit_rate_window: bitrate::DEFAULT_WINDOW,
+                include_loudness: false,
+                include_interval_detection: false,
+                include_crop_detection: false,
+                include_integrity_check: false,
+                include_continuity_analysis: false,
+                include_caption_detection: false,
+                include_subtitle_analysis: false,
+                include_stream_hashes: false,
+                include_frame_hashes: false,
+                verbose: false,
+                tag_value_width: None,
+                tag_value_max_length: None,
             },
             path: path.to_str().unwrap().to_string(),
             file_name,
@@ -203,8 +324,14 @@ impl MediaFileMetadata {
             file_size_base10,
             file_size_base2,
             hash: None,
+            checksums: BTreeMap::new(),
             title,
             container_format,
+            mp4_layout,
+            fast_start,
+            fragmented,
+            matroska,
+            music,
             _duration,
             duration,
             width,
@@ -214,6 +341,9 @@ impl MediaFileMetadata {
             sample_aspect_ratio,
             _display_aspect_ratio,
             display_aspect_ratio,
+            crop_area: None,
+            _effective_aspect_ratio: None,
+            effective_aspect_ratio: None,
             _scan_type,
             scan_type,
             _frame_rate,
@@ -222,23 +352,366 @@ impl MediaFileMetadata {
             bit_rate,
             _streams_metadata,
             streams_metadata_rendered,
+            programs,
+            programs_rendered,
+            gop_structures: Vec::new(),
+            gop_structures_rendered: Vec::new(),
+            bit_rate_profiles: Vec::new(),
+            bit_rate_profiles_rendered: Vec::new(),
+            detected_intervals: Vec::new(),
+            detected_intervals_rendered: Vec::new(),
+            integrity_issues: Vec::new(),
+            integrity_issues_rendered: Vec::new(),
+            continuity_reports: Vec::new(),
+            continuity_reports_rendered: Vec::new(),
+            stream_hashes_rendered: Vec::new(),
             tags,
             filtered_tags,
             streams_tags,
             streams_filtered_tags,
+            normalized_tags,
         })
     }
 
+    // Includes the SHA-256 checksum.
     pub fn include_checksum(&mut self, on: bool) -> io::Result<&mut MediaFileMetadata> {
         if on {
+            self.include_checksums(&[ChecksumAlgorithm::Sha256])
+        } else {
+            self.include_checksums(&[])
+        }
+    }
+
+    // Includes checksums with each of the algorithms, computed in a single
+    // pass over the file.
+    pub fn include_checksums(
+        &mut self,
+        algorithms: &[ChecksumAlgorithm],
+    ) -> io::Result<&mut MediaFileMetadata> {
+        if !algorithms.is_empty() {
             self.options.include_checksum = true;
-            self.hash = Some(util::sha256_hash(Path::new(&self.path))?);
+            self.checksums = util::checksums(Path::new(&self.path), algorithms)?;
         } else {
             self.options.include_checksum = false;
+            self.checksums.clear();
+        }
+        self.hash = self.checksums.get(&ChecksumAlgorithm::Sha256).cloned();
+        Ok(self)
+    }
+
+    pub fn include_gop_analysis(&mut self, on: bool) -> io::Result<&mut MediaFileMetadata> {
+        if on {
+            self.options.include_gop_analysis = true;
+            let mut format_ctx = ffmpeg::format::input(&self.path)?;
+            self.gop_structures = gop::analyze_gops(&mut format_ctx)?;
+            self.gop_structures_rendered = self
+                .gop_structures
+                .iter()
+                .map(|g| {
+                    g.render_default().unwrap_or_else(|_| {
+                        panic!("failed to render GOP structure for stream #{}", g.index)
+                    })
+                })
+                .collect();
+        } else {
+            self.options.include_gop_analysis = false;
+        }
+        Ok(self)
+    }
+
+    // window is the length of each measurement window, in seconds.
+    pub fn include_bit_rate_profile(
+        &mut self,
+        on: bool,
+        window: f64,
+    ) -> io::Result<&mut MediaFileMetadata> {
+        if on {
+            self.options.include_bit_rate_profile = true;
+            self.options.bit_rate_window = window;
+            let mut format_ctx = ffmpeg::format::input(&self.path)?;
+            self.bit_rate_profiles = bitrate::analyze_bit_rates(&mut format_ctx, window)?;
+            self.bit_rate_profiles_rendered = self
+                .bit_rate_profiles
+                .iter()
+                .map(|p| {
+                    p.render_default().unwrap_or_else(|_| {
+                        panic!("failed to render bit rate profile for stream #{}", p.index)
+                    })
+                })
+                .collect();
+        } else {
+            self.options.include_bit_rate_profile = false;
+        }
+        Ok(self)
+    }
+
+    pub fn include_loudness(&mut self, on: bool) -> io::Result<&mut MediaFileMetadata> {
+        let measurements = if on {
+            let mut format_ctx = ffmpeg::format::input(&self.path)?;
+            loudness::measure_loudness(&mut format_ctx)?
+        } else {
+            Vec::new()
+        };
+        self.options.include_loudness = on;
+        for m in self._streams_metadata.iter_mut() {
+            if let StreamMetadata::AudioMetadata(audio) = m {
+                audio.loudness = measurements
+                    .iter()
+                    .find(|(index, _)| *index == audio.index)
+                    .map(|(_, l)| l.clone());
+            }
+        }
+        self.streams_metadata_rendered = render_streams_metadata(&self._streams_metadata);
+        self.update_decode_frames();
+        Ok(self)
+    }
+
+    // Detects silence in audio streams, and black or frozen pictures in
+    // video streams.
+    pub fn include_interval_detection(
+        &mut self,
+        on: bool,
+        thresholds: &DetectionThresholds,
+    ) -> io::Result<&mut MediaFileMetadata> {
+        if on {
+            self.options.include_interval_detection = true;
+            let mut format_ctx = ffmpeg::format::input(&self.path)?;
+            self.detected_intervals = detect::detect_intervals(&mut format_ctx, thresholds)?;
+            self.detected_intervals_rendered = self
+                .detected_intervals
+                .iter()
+                .map(|i| {
+                    i.render_default().unwrap_or_else(|_| {
+                        panic!("failed to render detected interval for stream #{}", i.index)
+                    })
+                })
+                .collect();
+        } else {
+            self.options.include_interval_detection = false;
+        }
+        self.update_decode_frames();
+        Ok(self)
+    }
+
+    // Counts the events of subtitle streams, and guesses the character
+    // encoding of text subtitles.
+    pub fn include_subtitle_analysis(&mut self, on: bool) -> io::Result<&mut MediaFileMetadata> {
+        let mut events = if on {
+            let mut format_ctx = ffmpeg::format::input(&self.path)?;
+            let text_indices = self
+                ._streams_metadata
+                .iter()
+                .filter_map(|m| match m {
+                    StreamMetadata::SubtitleMetadata(s) if s._kind == Some(SubtitleKind::Text) => {
+                        Some(s.index)
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            subtitles::analyze_subtitles(&mut format_ctx, |i| text_indices.contains(&i))?
+        } else {
+            HashMap::new()
+        };
+        self.options.include_subtitle_analysis = on;
+        for m in self._streams_metadata.iter_mut() {
+            if let StreamMetadata::SubtitleMetadata(subtitle) = m {
+                subtitle.events = events.remove(&subtitle.index);
+            }
+        }
+        self.streams_metadata_rendered = render_streams_metadata(&self._streams_metadata);
+        Ok(self)
+    }
+
+    // Hashes the packet payloads of each stream, and if decode_frames is
+    // set, the decoded frames of audio and video streams too, so that
+    // streams can be compared across containers.
+    pub fn include_stream_hashes(
+        &mut self,
+        on: bool,
+        decode_frames: bool,
+    ) -> io::Result<&mut MediaFileMetadata> {
+        let hashes = if on {
+            let mut format_ctx = ffmpeg::format::input(&self.path)?;
+            streamhash::hash_streams(&mut format_ctx, decode_frames)?
+        } else {
+            Vec::new()
+        };
+        self.options.include_stream_hashes = on;
+        self.options.include_frame_hashes = on && decode_frames;
+        for m in self._streams_metadata.iter_mut() {
+            let index = m.index();
+            m.set_content_hash(hashes.iter().find(|h| h.index == index).cloned());
+        }
+        self.stream_hashes_rendered = self
+            ._streams_metadata
+            .iter()
+            .filter_map(|m| m.content_hash())
+            .map(|h| {
+                h.render_default().unwrap_or_else(|_| {
+                    panic!("failed to render stream hash for stream #{}", h.index)
+                })
+            })
+            .collect();
+        self.update_decode_frames();
+        Ok(self)
+    }
+
+    // Detects A53 closed captions embedded in video streams (attached
+    // pictures excluded) from stream side data or sampled decoded frames.
+    pub fn include_caption_detection(&mut self, on: bool) -> io::Result<&mut MediaFileMetadata> {
+        let mut format_ctx = if on {
+            Some(ffmpeg::format::input(&self.path)?)
+        } else {
+            None
+        };
+        self.options.include_caption_detection = on;
+        for m in self._streams_metadata.iter_mut() {
+            if let StreamMetadata::VideoMetadata(video) = m {
+                video.closed_captions = match format_ctx.as_mut() {
+                    Some(format_ctx) => captions::detect_closed_captions(format_ctx, video.index)?,
+                    None => None,
+                };
+            }
+        }
+        self.streams_metadata_rendered = render_streams_metadata(&self._streams_metadata);
+        self.update_decode_frames();
+        Ok(self)
+    }
+
+    // Detects letterboxing or pillarboxing in the best video stream by
+    // sampling decoded frames.
+    pub fn include_crop_detection(&mut self, on: bool) -> io::Result<&mut MediaFileMetadata> {
+        if on {
+            self.options.include_crop_detection = true;
+            let mut format_ctx = ffmpeg::format::input(&self.path)?;
+            let best_vstream_index = format_ctx.streams().best(Type::Video).map(|s| s.index());
+            self.crop_area = match best_vstream_index {
+                Some(index) => crop::detect_crop(&mut format_ctx, index)?,
+                None => None,
+            };
+            let sar = self._sample_aspect_ratio.unwrap_or(Rational(1, 1));
+            self._effective_aspect_ratio = self.crop_area.as_ref().map(|a| a.aspect_ratio(sar));
+            self.effective_aspect_ratio = self
+                ._effective_aspect_ratio
+                .map(|r| format!("{:.2}:1", f64::from(r)));
+        } else {
+            self.options.include_crop_detection = false;
+        }
+        self.update_decode_frames();
+        Ok(self)
+    }
+
+    // Decodes every stream end to end, looking for read and decode errors,
+    // corrupt packets, timestamp problems and truncation.
+    pub fn include_integrity_check(&mut self, on: bool) -> io::Result<&mut MediaFileMetadata> {
+        if on {
+            self.options.include_integrity_check = true;
+            let mut format_ctx = ffmpeg::format::input(&self.path)?;
+            self.integrity_issues = verify::verify_integrity(&mut format_ctx)?;
+            self.integrity_issues_rendered = self
+                .integrity_issues
+                .iter()
+                .map(|i| {
+                    i.render_default().unwrap_or_else(|_| {
+                        panic!("failed to render integrity issue: {}", i.description)
+                    })
+                })
+                .collect();
+        } else {
+            self.options.include_integrity_check = false;
+        }
+        self.update_decode_frames();
+        Ok(self)
+    }
+
+    fn update_decode_frames(&mut self) {
+        let o = &self.options;
+        let decode_frames = o.include_loudness
+            || o.include_interval_detection
+            || o.include_caption_detection
+            || o.include_crop_detection
+            || o.include_integrity_check
+            || o.include_frame_hashes;
+        self.options.decode_frames = decode_frames;
+    }
+
+    // Whether the integrity check, if performed, found any issue.
+    pub fn is_damaged(&self) -> bool {
+        !self.integrity_issues.is_empty()
+    }
+
+    // Looks for backwards timestamps, gaps and discontinuities, and for
+    // transport streams, PCR jumps and continuity counter errors.
+    pub fn include_continuity_analysis(&mut self, on: bool) -> io::Result<&mut MediaFileMetadata> {
+        if on {
+            self.options.include_continuity_analysis = true;
+            let mut format_ctx = ffmpeg::format::input(&self.path)?;
+            self.continuity_reports =
+                continuity::analyze_continuity(&mut format_ctx, Path::new(&self.path))?;
+            self.continuity_reports_rendered = self
+                .continuity_reports
+                .iter()
+                .map(|r| {
+                    r.render_default().unwrap_or_else(|_| {
+                        panic!("failed to render continuity report for {}", r.location)
+                    })
+                })
+                .collect();
+        } else {
+            self.options.include_continuity_analysis = false;
         }
         Ok(self)
     }
 
+    // Renders streams with extra details, e.g., codec tags.
+    pub fn verbose(&mut self, on: bool) -> &mut MediaFileMetadata {
+        self.options.verbose = on;
+        for m in self._streams_metadata.iter_mut() {
+            m.set_verbose(on);
+        }
+        self.streams_metadata_rendered = render_streams_metadata(&self._streams_metadata);
+        self
+    }
+
+    // Refilters tags with a custom predicate in place of the built-in
+    // rules, e.g., TagRules::is_boring.
+    pub fn filter_tags<F: Fn(&str) -> bool>(&mut self, is_boring: F) -> &mut MediaFileMetadata {
+        self.filtered_tags = tags::filter_tags(&self.tags, &is_boring);
+        self.streams_filtered_tags = self
+            .streams_tags
+            .iter()
+            .map(|s| StreamTags {
+                index: s.index,
+                tags: tags::filter_tags(&s.tags, &is_boring),
+            })
+            .collect();
+        self
+    }
+
+    // Wraps tag values to fit within width columns (e.g., of the terminal),
+    // with continuation lines indented under the first.
+    pub fn wrap_tag_values(&mut self, width: Option<usize>) -> &mut MediaFileMetadata {
+        self.options.tag_value_width = width;
+        self
+    }
+
+    // Truncates tag values longer than max_length characters, e.g., lyrics.
+    pub fn truncate_tag_values(&mut self, max_length: Option<usize>) -> &mut MediaFileMetadata {
+        self.options.tag_value_max_length = max_length;
+        self
+    }
+
+    // Lyrics from container and stream tags, followed by synchronized
+    // lyrics from ID3 SYLT frames, which libavformat does not expose.
+    pub fn lyrics(&self) -> io::Result<Vec<Lyrics>> {
+        let mut lyrics = lyrics::lyrics_from_tags(&self.tags);
+        for stream_tags in &self.streams_tags {
+            lyrics.extend(lyrics::lyrics_from_tags(&stream_tags.tags));
+        }
+        lyrics.extend(lyrics::read_synced_lyrics(Path::new(&self.path))?);
+        Ok(lyrics)
+    }
+
     pub fn include_tags(&mut self, on: bool) -> &mut MediaFileMetadata {
         if on {
             self.options.include_tags = true;
@@ -258,3 +731,13 @@ impl MediaFileMetadata {
         self
     }
 }
+
+fn render_streams_metadata(streams_metadata: &[StreamMetadata]) -> Vec<String> {
+    streams_metadata
+        .iter()
+        .map(|m| {
+            m.render_default()
+                .unwrap_or_else(|_| panic!("failed to render metadata for stream #{}", m.index()))
+        })
+        .collect()
+}
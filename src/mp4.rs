@@ -0,0 +1,200 @@
+// A minimal ISO base media file format (MP4, QuickTime and friends) box
+// walker, for properties of the file layout libavformat does not expose.
+
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+// Brands of CMAF (ISO/IEC 23000-19) tracks and fragments.
+const CMAF_BRANDS: [&[u8; 4]; 2] = [b"cmfc", b"cmf2"];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BoxHeader {
+    pub box_type: [u8; 4],
+    pub offset: u64,
+    // Total size including the header.
+    pub size: u64,
+    pub header_size: u64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Mp4Layout {
+    // Whether the moov box precedes all media data.
+    pub fast_start: bool,
+    // Whether there are movie fragments (moof boxes, or an mvex box
+    // announcing them).
+    pub fragmented: bool,
+    pub cmaf: bool,
+    pub top_level_boxes: Vec<String>,
+}
+
+// Reads the headers of consecutive boxes between start and end, without
+// descending into them.
+pub fn read_box_headers<R: Read + Seek>(
+    reader: &mut R,
+    start: u64,
+    end: u64,
+) -> io::Result<Vec<BoxHeader>> {
+    let mut headers = Vec::new();
+    let mut offset = start;
+    while offset.checked_add(8).is_some_and(|o| o <= end) {
+        reader.seek(SeekFrom::Start(offset))?;
+        let mut buf = [0u8; 8];
+        reader.read_exact(&mut buf)?;
+        let mut size = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) as u64;
+        let mut box_type = [0u8; 4];
+        box_type.copy_from_slice(&buf[4..8]);
+        let mut header_size = 8;
+        if size == 1 {
+            let mut large = [0u8; 8];
+            reader.read_exact(&mut large)?;
+            size = u64::from_be_bytes(large);
+            header_size = 16;
+        } else if size == 0 {
+            // The box extends to the end.
+            size = end - offset;
+        }
+        if size < header_size {
+            debug!("invalid size {} of box at offset {}", size, offset);
+            break;
+        }
+        headers.push(BoxHeader {
+            box_type,
+            offset,
+            size,
+            header_size,
+        });
+        offset = match offset.checked_add(size) {
+            Some(next) => next,
+            None => {
+                debug!("size {} of box at offset {} out of range", size, offset);
+                break;
+            }
+        };
+    }
+    Ok(headers)
+}
+
+fn analyze<R: Read + Seek>(reader: &mut R, length: u64) -> io::Result<Mp4Layout> {
+    let boxes = read_box_headers(reader, 0, length)?;
+    let position = |box_type: &[u8; 4]| boxes.iter().position(|b| &b.box_type == box_type);
+
+    let moov = position(b"moov");
+    let first_mdat = position(b"mdat");
+    let fast_start = match (moov, first_mdat) {
+        (Some(moov), Some(mdat)) => moov < mdat,
+        (Some(_), None) => true,
+        (None, _) => false,
+    };
+
+    let has_mvex = match moov {
+        Some(i) => {
+            let moov = &boxes[i];
+            let start = moov.offset + moov.header_size;
+            let end = (moov.offset + moov.size).min(length);
+            read_box_headers(reader, start, end)?
+                .iter()
+                .any(|b| &b.box_type == b"mvex")
+        }
+        None => false,
+    };
+    let fragmented = position(b"moof").is_some() || has_mvex;
+
+    // Compatible brands follow the major brand and minor version in ftyp
+    // (or styp for segments).
+    let mut brands = Vec::new();
+    if let Some(ftyp) = boxes
+        .iter()
+        .find(|b| &b.box_type == b"ftyp" || &b.box_type == b"styp")
+    {
+        let payload_size = (ftyp.size - ftyp.header_size).min(1024) as usize;
+        let mut payload = vec![0u8; payload_size];
+        reader.seek(SeekFrom::Start(ftyp.offset + ftyp.header_size))?;
+        reader.read_exact(&mut payload)?;
+        brands = payload
+            .chunks_exact(4)
+            .enumerate()
+            .filter(|&(i, _)| i != 1)
+            .map(|(_, brand)| brand.to_vec())
+            .collect();
+    }
+    let cmaf = fragmented
+        && brands
+            .iter()
+            .any(|b| CMAF_BRANDS.iter().any(|c| c[..] == b[..]));
+
+    Ok(Mp4Layout {
+        fast_start,
+        fragmented,
+        cmaf,
+        top_level_boxes: boxes
+            .iter()
+            .map(|b| String::from_utf8_lossy(&b.box_type).into_owned())
+            .collect(),
+    })
+}
+
+pub fn analyze_layout(path: &Path) -> io::Result<Mp4Layout> {
+    let file = File::open(path)?;
+    let length = file.metadata()?.len();
+    analyze(&mut BufReader::new(file), length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn mp4_box(box_type: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut b = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+        b.extend_from_slice(box_type);
+        b.extend_from_slice(payload);
+        b
+    }
+
+    #[test]
+    fn fast_start_and_fragmentation() {
+        let ftyp = mp4_box(b"ftyp", b"iso6\0\0\0\0iso6cmfc");
+        let moov = mp4_box(b"moov", &mp4_box(b"mvex", &[]));
+        let mut data = ftyp.clone();
+        data.extend(&moov);
+        data.extend(mp4_box(b"moof", &[]));
+        data.extend(mp4_box(b"mdat", &[0; 16]));
+        let layout = analyze(&mut Cursor::new(&data), data.len() as u64).unwrap();
+        assert!(layout.fast_start);
+        assert!(layout.fragmented);
+        assert!(layout.cmaf);
+        assert_eq!(vec!["ftyp", "moov", "moof", "mdat"], layout.top_level_boxes);
+
+        let mut data = ftyp;
+        data.extend(mp4_box(b"mdat", &[0; 16]));
+        data.extend(mp4_box(b"moov", &[]));
+        let layout = analyze(&mut Cursor::new(&data), data.len() as u64).unwrap();
+        assert!(!layout.fast_start);
+        assert!(!layout.fragmented);
+        assert!(!layout.cmaf);
+    }
+
+    #[test]
+    fn malformed_box_sizes() {
+        // A largesize running past the end of u64, after which nothing can
+        // follow.
+        let mut data = mp4_box(b"ftyp", b"isom\0\0\0\0");
+        data.extend(1u32.to_be_bytes());
+        data.extend(b"mdat");
+        data.extend(u64::MAX.to_be_bytes());
+        data.extend(mp4_box(b"moov", &[]));
+        let boxes = read_box_headers(&mut Cursor::new(&data), 0, u64::MAX).unwrap();
+        assert_eq!(2, boxes.len());
+        assert_eq!(u64::MAX, boxes[1].size);
+
+        // A size smaller than the header, and a box running past the end.
+        let mut data = mp4_box(b"ftyp", b"isom\0\0\0\0");
+        data.extend(4u32.to_be_bytes());
+        data.extend(b"free");
+        let boxes = read_box_headers(&mut Cursor::new(&data), 0, data.len() as u64).unwrap();
+        assert_eq!(1, boxes.len());
+        let boxes = read_box_headers(&mut Cursor::new(&data[..12]), 0, 12).unwrap();
+        assert_eq!(1, boxes.len());
+    }
+}
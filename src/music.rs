@@ -0,0 +1,228 @@
+// Interpretation of music tagging conventions: ReplayGain and MusicBrainz
+// identifiers, as written by taggers like foobar2000 and MusicBrainz
+// Picard. Keys differ between Vorbis comments (REPLAYGAIN_TRACK_GAIN,
+// MUSICBRAINZ_TRACKID), ID3 TXXX frames and MP4 freeform atoms
+// ("MusicBrainz Track Id"), so they are compared with case, spaces and
+// underscores ignored.
+
+use regex::Regex;
+
+use crate::tags::Tags;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct MusicBrainzId {
+    // What is identified, e.g., "Recording".
+    pub entity: String,
+    // E.g., "Recording ID".
+    pub label: String,
+    // Possibly several IDs separated by "; ", e.g., for multiple artists.
+    pub id: String,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct MusicInfo {
+    #[serde(skip_serializing)]
+    pub _track_gain: Option<f64>,
+    pub track_gain: Option<String>,
+    #[serde(skip_serializing)]
+    pub _track_peak: Option<f64>,
+    pub track_peak: Option<String>,
+    #[serde(skip_serializing)]
+    pub _album_gain: Option<f64>,
+    pub album_gain: Option<String>,
+    #[serde(skip_serializing)]
+    pub _album_peak: Option<f64>,
+    pub album_peak: Option<String>,
+    #[serde(skip_serializing)]
+    pub _reference_loudness: Option<f64>,
+    pub reference_loudness: Option<String>,
+    pub musicbrainz_ids: Vec<MusicBrainzId>,
+}
+
+// Normalized keys of MusicBrainz tags and the entities they identify, in
+// display order.
+const MUSICBRAINZ_KEYS: [(&str, &str); 8] = [
+    ("musicbrainztrackid", "Recording"),
+    ("musicbrainzreleasetrackid", "Track"),
+    ("musicbrainzalbumid", "Release"),
+    ("musicbrainzreleasegroupid", "Release group"),
+    ("musicbrainzartistid", "Artist"),
+    ("musicbrainzalbumartistid", "Album artist"),
+    ("musicbrainzworkid", "Work"),
+    ("musicbrainzdiscid", "Disc"),
+];
+
+fn normalize_key(key: &str) -> String {
+    key.chars()
+        .filter(|&c| c != ' ' && c != '_')
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+// Parses values like "-6.54 dB" or "+1.2 dB".
+fn parse_gain(value: &str) -> Option<f64> {
+    let value = value.trim().to_ascii_lowercase();
+    value
+        .strip_suffix("db")
+        .unwrap_or(&value)
+        .trim()
+        .parse::<f64>()
+        .ok()
+}
+
+fn parse_peak(value: &str) -> Option<f64> {
+    value.trim().parse::<f64>().ok().filter(|&p| p >= 0f64)
+}
+
+// MusicBrainz identifiers are UUIDs, except for disc IDs, which are 28
+// characters of a modified base64. Multiple values are separated by
+// slashes or semicolons.
+fn parse_musicbrainz_ids(key: &str, value: &str) -> Option<String> {
+    lazy_static! {
+        static ref UUID_PATTERN: Regex =
+            Regex::new(r"(?i)^[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}$")
+                .unwrap();
+        static ref DISC_ID_PATTERN: Regex = Regex::new(r"^[0-9A-Za-z._-]{28}$").unwrap();
+    }
+    let pattern: &Regex = if key == "musicbrainzdiscid" {
+        &DISC_ID_PATTERN
+    } else {
+        &UUID_PATTERN
+    };
+    let ids = value
+        .split(['/', ';'])
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .collect::<Vec<_>>();
+    if !ids.is_empty() && ids.iter().all(|id| pattern.is_match(id)) {
+        Some(ids.join("; "))
+    } else {
+        None
+    }
+}
+
+impl MusicInfo {
+    // Collects music tags from a number of sources in order of precedence;
+    // None if there are none.
+    pub fn new(sources: &[&Tags]) -> Option<MusicInfo> {
+        let mut info = MusicInfo::default();
+        let mut ids: Vec<(usize, MusicBrainzId)> = Vec::new();
+        for (key, value) in sources.iter().flat_map(|tags| tags.iter()) {
+            let key = normalize_key(key);
+            match key.as_str() {
+                "replaygaintrackgain" if info._track_gain.is_none() => {
+                    info._track_gain = parse_gain(value)
+                }
+                "replaygaintrackpeak" if info._track_peak.is_none() => {
+                    info._track_peak = parse_peak(value)
+                }
+                "replaygainalbumgain" if info._album_gain.is_none() => {
+                    info._album_gain = parse_gain(value)
+                }
+                "replaygainalbumpeak" if info._album_peak.is_none() => {
+                    info._album_peak = parse_peak(value)
+                }
+                "replaygainreferenceloudness" if info._reference_loudness.is_none() => {
+                    info._reference_loudness = parse_gain(value)
+                }
+                _ => {
+                    let position = MUSICBRAINZ_KEYS.iter().position(|&(k, _)| k == key);
+                    if let Some(position) = position {
+                        if ids.iter().any(|&(p, _)| p == position) {
+                            continue;
+                        }
+                        if let Some(id) = parse_musicbrainz_ids(&key, value) {
+                            let entity = MUSICBRAINZ_KEYS[position].1;
+                            ids.push((
+                                position,
+                                MusicBrainzId {
+                                    entity: entity.to_string(),
+                                    label: format!("{} ID", entity),
+                                    id,
+                                },
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+        ids.sort_by_key(|&(p, _)| p);
+        info.musicbrainz_ids = ids.into_iter().map(|(_, id)| id).collect();
+
+        info.track_gain = info._track_gain.map(|g| format!("{:+.2} dB", g));
+        info.track_peak = info._track_peak.map(|p| format!("{:.6}", p));
+        info.album_gain = info._album_gain.map(|g| format!("{:+.2} dB", g));
+        info.album_peak = info._album_peak.map(|p| format!("{:.6}", p));
+        info.reference_loudness = info._reference_loudness.map(|l| format!("{:.1} dB", l));
+
+        if info._track_gain.is_none()
+            && info._track_peak.is_none()
+            && info._album_gain.is_none()
+            && info._album_peak.is_none()
+            && info.musicbrainz_ids.is_empty()
+        {
+            None
+        } else {
+            Some(info)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(pairs: &[(&str, &str)]) -> Tags {
+        pairs
+            .iter()
+            .map(|&(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn replay_gain() {
+        let file_tags = tags(&[
+            ("REPLAYGAIN_TRACK_GAIN", "-6.54 dB"),
+            ("REPLAYGAIN_TRACK_PEAK", "0.988547"),
+            ("replaygain_album_gain", "+1.5 dB"),
+            ("REPLAYGAIN_ALBUM_PEAK", "not a number"),
+        ]);
+        let info = MusicInfo::new(&[&file_tags]).unwrap();
+        assert_eq!(Some(-6.54), info._track_gain);
+        assert_eq!(Some("-6.54 dB"), info.track_gain.as_deref());
+        assert_eq!(Some("0.988547"), info.track_peak.as_deref());
+        assert_eq!(Some("+1.50 dB"), info.album_gain.as_deref());
+        assert_eq!(None, info._album_peak);
+    }
+
+    #[test]
+    fn musicbrainz_ids() {
+        let file_tags = tags(&[
+            (
+                "MusicBrainz Album Id",
+                "6d9f3a3e-0f7c-4b6e-9c1e-5a0e6f1f4c2b",
+            ),
+            (
+                "MUSICBRAINZ_TRACKID",
+                "0B5C7F0E-2F39-4B1C-A7A6-9E9F2C1D3E4F",
+            ),
+            (
+                "MUSICBRAINZ_ARTISTID",
+                "1f9df192-a621-4f54-8850-2c5373b7eac9/5b11f4ce-a62d-471e-81fc-a69a8278c7da",
+            ),
+            ("MUSICBRAINZ_WORKID", "not-a-uuid"),
+        ]);
+        let info = MusicInfo::new(&[&file_tags]).unwrap();
+        let entities = info
+            .musicbrainz_ids
+            .iter()
+            .map(|id| id.entity.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["Recording", "Release", "Artist"], entities);
+        assert_eq!(
+            "1f9df192-a621-4f54-8850-2c5373b7eac9; 5b11f4ce-a62d-471e-81fc-a69a8278c7da",
+            info.musicbrainz_ids[2].id
+        );
+        assert!(MusicInfo::new(&[&tags(&[("title", "Title")])]).is_none());
+    }
+}
@@ -5,13 +5,39 @@
 
 use crate::ffmpeg;
 use crate::ffmpeg::codec::parameters::Parameters;
-use crate::ffmpeg::codec::profile::{Profile, AAC, H264, HEVC, VP9};
+use crate::ffmpeg::codec::profile::{Profile, AAC, DTS, H264, HEVC, MPEG2, VC1, VP9};
 use crate::ffmpeg::codec::Id;
 use crate::ffmpeg::format::Input;
 use std::ffi::CStr;
 use std::path::Path;
+use std::slice;
 use std::str::from_utf8_unchecked;
 
+// Profiles not wrapped by ffmpeg::codec::profile::Profile, from
+// libavcodec/defs.h.
+const AV1_PROFILE_MAIN: i32 = 0;
+const AV1_PROFILE_HIGH: i32 = 1;
+const AV1_PROFILE_PROFESSIONAL: i32 = 2;
+const DNXHD_PROFILE_DNXHD: i32 = 0;
+const DNXHD_PROFILE_DNXHR_LB: i32 = 1;
+const DNXHD_PROFILE_DNXHR_SQ: i32 = 2;
+const DNXHD_PROFILE_DNXHR_HQ: i32 = 3;
+const DNXHD_PROFILE_DNXHR_HQX: i32 = 4;
+const DNXHD_PROFILE_DNXHR_444: i32 = 5;
+const PRORES_PROFILE_PROXY: i32 = 0;
+const PRORES_PROFILE_LT: i32 = 1;
+const PRORES_PROFILE_STANDARD: i32 = 2;
+const PRORES_PROFILE_HQ: i32 = 3;
+const PRORES_PROFILE_4444: i32 = 4;
+const PRORES_PROFILE_XQ: i32 = 5;
+const VVC_PROFILE_MAIN_10: i32 = 1;
+const VVC_PROFILE_MAIN_10_444: i32 = 33;
+const DTS_PROFILE_HD_MA_X: i32 = 61;
+const DTS_PROFILE_HD_MA_X_IMAX: i32 = 62;
+const EAC3_PROFILE_DDP_ATMOS: i32 = 30;
+const TRUEHD_PROFILE_ATMOS: i32 = 30;
+const LEVEL_UNKNOWN: i32 = -99;
+
 // Assumes ASCII!
 fn capitalize(s: String) -> String {
     if s == "" {
@@ -94,14 +120,24 @@ pub fn codec_name(codec_id: Id) -> String {
         "h264" => "H.264",
         // "H.265 / HEVC (High Efficiency Video Coding)"
         "hevc" => "HEVC",
+        // "Alliance for Open Media AV1"
+        "av1" => "AV1",
+        // "VC3/DNxHD"
+        "dnxhd" => "Avid DNxHD",
         // "MPEG-4 part 2"
         "mpeg4" => "MPEG-4 Part 2",
         // "PNG (Portable Network Graphics) image"
         "png" => "PNG",
+        // "Apple ProRes (iCodec Pro)"
+        "prores" => "Apple ProRes",
+        // "SMPTE VC-1"
+        "vc1" => "VC-1",
         // "On2 VP8"
         "vp8" => "VP8",
         // "Google VP9"
         "vp9" => "VP9",
+        // "H.266 / VVC (Versatile Video Coding)"
+        "vvc" => "VVC",
 
         // Audio codecs
         // "AAC (Advanced Audio Coding)"
@@ -110,6 +146,10 @@ pub fn codec_name(codec_id: Id) -> String {
         "ac3" => "Dolby AC-3",
         // "Cook / Cooker / Gecko (RealAudio G2)"
         "cook" => "Cook (RealAudio G2)",
+        // "DCA (DTS Coherent Acoustics)"
+        "dts" => "DTS",
+        // "ATSC A/52B (AC-3, E-AC-3)"
+        "eac3" => "Dolby E-AC-3",
         // "FLAC (Free Lossless Audio Codec)"
         "flac" => "FLAC",
         // "MP3 (MPEG audio layer 3)"
@@ -120,6 +160,8 @@ pub fn codec_name(codec_id: Id) -> String {
         "ra_144" => "RealAudio 1.0",
         // "RealAudio 2.0 (28.8K)"
         "ra_288" => "RealAudio 2.0",
+        // "TrueHD"
+        "truehd" => "Dolby TrueHD",
 
         // Subtitle codecs
         // "ASS (Advanced SSA) subtitle"
@@ -146,10 +188,167 @@ pub fn codec_name(codec_id: Id) -> String {
     .to_string()
 }
 
+// AV1 levels are X.Y where X = 2 + seq_level_idx / 4 and Y = seq_level_idx
+// % 4; 31 is the unconstrained "maximum parameters" level.
+fn av1_level_name(seq_level_idx: i32) -> String {
+    match seq_level_idx {
+        31 => "Max".to_string(),
+        idx => format!("{}.{}", 2 + idx / 4, idx % 4),
+    }
+}
+
+// VVC general_level_idc is 16 times the major level plus 3 times the minor
+// level, e.g., 83 for level 5.1.
+fn vvc_level_name(level_idc: i32) -> String {
+    if level_idc % 16 == 0 {
+        format!("{}", level_idc / 16)
+    } else {
+        format!("{}.{}", level_idc / 16, level_idc % 16 / 3)
+    }
+}
+
+// Level names of MPEG-2 video by the level field of
+// profile_and_level_indication; the 4:2:2 profile (an escaped profile)
+// has its own level codes.
+fn mpeg2_level_name(profile: Profile, level: i32) -> &'static str {
+    match (profile, level) {
+        (Profile::MPEG2(MPEG2::_422), 2) => "High Level",
+        (Profile::MPEG2(MPEG2::_422), 5) => "Main Level",
+        (_, 4) => "High Level",
+        (_, 6) => "High 1440 Level",
+        (_, 8) => "Main Level",
+        (_, 10) => "Low Level",
+        _ => "Unknown Level",
+    }
+}
+
+// The codec private data (extradata) of the stream, if any.
+fn extradata(codec_par: &Parameters) -> &[u8] {
+    unsafe {
+        let par = &*codec_par.as_ptr();
+        if par.extradata.is_null() || par.extradata_size <= 0 {
+            return &[];
+        }
+        slice::from_raw_parts(par.extradata, par.extradata_size as usize)
+    }
+}
+
+// The AV1 codec configuration record (av1C), stored as extradata by MP4 and
+// Matroska, is the only place the tier is available.
+fn av1_tier(av1c: &[u8]) -> Option<&'static str> {
+    // marker (1) | version (7), seq_profile (3) | seq_level_idx_0 (5),
+    // seq_tier_0 (1) | ...
+    if av1c.len() < 4 || av1c[0] != 0x81 {
+        return None;
+    }
+    Some(if av1c[2] >> 7 == 0 {
+        "Main tier"
+    } else {
+        "High tier"
+    })
+}
+
+fn av1_profile_name(raw_profile: i32) -> &'static str {
+    match raw_profile {
+        AV1_PROFILE_MAIN => "Main Profile",
+        AV1_PROFILE_HIGH => "High Profile",
+        AV1_PROFILE_PROFESSIONAL => "Professional Profile",
+        _ => "Unknown Profile",
+    }
+}
+
+fn vvc_profile_name(raw_profile: i32) -> &'static str {
+    match raw_profile {
+        VVC_PROFILE_MAIN_10 => "Main 10 Profile",
+        VVC_PROFILE_MAIN_10_444 => "Main 10 4:4:4 Profile",
+        _ => "Unknown Profile",
+    }
+}
+
+// ProRes variants by profile, which libavcodec derives from the codec tag
+// (apco, apcs, etc.).
+fn prores_variant(raw_profile: i32) -> Option<&'static str> {
+    match raw_profile {
+        PRORES_PROFILE_PROXY => Some("422 Proxy"),
+        PRORES_PROFILE_LT => Some("422 LT"),
+        PRORES_PROFILE_STANDARD => Some("422"),
+        PRORES_PROFILE_HQ => Some("422 HQ"),
+        PRORES_PROFILE_4444 => Some("4444"),
+        PRORES_PROFILE_XQ => Some("4444 XQ"),
+        _ => None,
+    }
+}
+
+// DNxHR shares the codec with DNxHD, and is told apart by profile.
+fn dnxhr_name(raw_profile: i32) -> Option<&'static str> {
+    match raw_profile {
+        DNXHD_PROFILE_DNXHD => None,
+        DNXHD_PROFILE_DNXHR_LB => Some("Avid DNxHR LB"),
+        DNXHD_PROFILE_DNXHR_SQ => Some("Avid DNxHR SQ"),
+        DNXHD_PROFILE_DNXHR_HQ => Some("Avid DNxHR HQ"),
+        DNXHD_PROFILE_DNXHR_HQX => Some("Avid DNxHR HQX"),
+        DNXHD_PROFILE_DNXHR_444 => Some("Avid DNxHR 444"),
+        _ => None,
+    }
+}
+
+fn dts_profile_name(raw_profile: i32) -> Option<&'static str> {
+    match (Profile::from((Id::DTS, raw_profile)), raw_profile) {
+        (Profile::DTS(DTS::Default), _) => Some("Core"),
+        (Profile::DTS(DTS::ES), _) => Some("ES"),
+        (Profile::DTS(DTS::_96_24), _) => Some("96/24"),
+        (Profile::DTS(DTS::HD_HRA), _) => Some("HD HRA"),
+        (Profile::DTS(DTS::HD_MA), _) => Some("HD MA"),
+        (Profile::DTS(DTS::Express), _) => Some("Express"),
+        (_, DTS_PROFILE_HD_MA_X) => Some("HD MA + DTS:X"),
+        (_, DTS_PROFILE_HD_MA_X_IMAX) => Some("HD MA + DTS:X IMAX"),
+        _ => None,
+    }
+}
+
+// Atmos is carried as joint object coding (JOC) in E-AC-3, and as an extra
+// substream in TrueHD.
+fn is_atmos(codec_id: Id, raw_profile: i32) -> bool {
+    match codec_id {
+        Id::EAC3 => raw_profile == EAC3_PROFILE_DDP_ATMOS,
+        Id::TRUEHD => raw_profile == TRUEHD_PROFILE_ATMOS,
+        _ => false,
+    }
+}
+
+// The channel mapping family of Opus, from the identification header
+// (OpusHead) stored as extradata. Family 0 (mono or stereo) is the
+// unremarkable default.
+fn opus_channel_mapping(header: &[u8]) -> Option<String> {
+    if header.len() < 19 || &header[..8] != b"OpusHead" {
+        return None;
+    }
+    let family = header[18];
+    let description = match family {
+        0 => return None,
+        1 => "Vorbis channel order",
+        2 => "ambisonics",
+        3 => "ambisonics with demixing",
+        255 => "discrete channels",
+        _ => "unknown",
+    };
+    Some(format!("mapping family {}: {}", family, description))
+}
+
+// Vorbis defines the channel order for up to 8 channels only.
+fn vorbis_channel_order(channels: i32) -> Option<&'static str> {
+    if channels > 8 {
+        Some("application-defined channel order")
+    } else {
+        None
+    }
+}
+
 pub fn codec_description(codec_par: &Parameters) -> String {
     let codec_id = codec_par.id();
     let name = codec_name(codec_id);
-    let profile = Profile::from((codec_id, unsafe { (*codec_par.as_ptr()).profile }));
+    let raw_profile = unsafe { (*codec_par.as_ptr()).profile };
+    let profile = Profile::from((codec_id, raw_profile));
     let level = unsafe { (*codec_par.as_ptr()).level };
     match codec_id {
         Id::H264 => {
@@ -217,6 +416,84 @@ pub fn codec_description(codec_par: &Parameters) -> String {
             format!("{} ({})", name, profile_name)
         }
 
+        Id::AV1 => {
+            let mut desc = format!("{} ({}", name, av1_profile_name(raw_profile));
+            if level != LEVEL_UNKNOWN {
+                desc += &format!(" level {}", av1_level_name(level));
+            }
+            if let Some(tier) = av1_tier(extradata(codec_par)) {
+                desc += &format!(", {}", tier);
+            }
+            desc + ")"
+        }
+
+        Id::VVC => {
+            let profile_name = vvc_profile_name(raw_profile);
+            if level == LEVEL_UNKNOWN {
+                format!("{} ({})", name, profile_name)
+            } else {
+                format!(
+                    "{} ({} level {})",
+                    name,
+                    profile_name,
+                    vvc_level_name(level)
+                )
+            }
+        }
+
+        Id::MPEG2VIDEO => {
+            let profile_name = if let Profile::MPEG2(p) = profile {
+                match p {
+                    MPEG2::_422 => "4:2:2 Profile",
+                    MPEG2::High => "High Profile",
+                    MPEG2::SS => "Spatially Scalable Profile",
+                    MPEG2::SNRScalable => "SNR Scalable Profile",
+                    MPEG2::Main => "Main Profile",
+                    MPEG2::Simple => "Simple Profile",
+                }
+            } else {
+                // Without a sequence extension, there is nothing to show.
+                return name;
+            };
+            format!(
+                "{} ({} @ {})",
+                name,
+                profile_name,
+                mpeg2_level_name(profile, level)
+            )
+        }
+
+        Id::VC1 => {
+            let profile_name = if let Profile::VC1(p) = profile {
+                match p {
+                    VC1::Simple => "Simple Profile",
+                    VC1::Main => "Main Profile",
+                    VC1::Complex => "Complex Profile",
+                    VC1::Advanced => "Advanced Profile",
+                }
+            } else {
+                "Unknown Profile"
+            };
+            // Only the advanced profile signals a level (L0 to L4) in the
+            // sequence header.
+            match profile {
+                Profile::VC1(VC1::Advanced) if level != LEVEL_UNKNOWN => {
+                    format!("{} ({} level L{})", name, profile_name, level)
+                }
+                _ => format!("{} ({})", name, profile_name),
+            }
+        }
+
+        Id::PRORES => match prores_variant(raw_profile) {
+            Some(variant) => format!("{} {}", name, variant),
+            None => name,
+        },
+
+        Id::DNXHD => match dnxhr_name(raw_profile) {
+            Some(dnxhr) => dnxhr.to_string(),
+            None => name,
+        },
+
         Id::AAC => {
             let profile_name = if let Profile::AAC(p) = profile {
                 match p {
@@ -237,6 +514,126 @@ pub fn codec_description(codec_par: &Parameters) -> String {
             format!("{} ({})", name, profile_name)
         }
 
+        Id::DTS => match dts_profile_name(raw_profile) {
+            Some(profile_name) => format!("{} ({})", name, profile_name),
+            None => name,
+        },
+
+        Id::EAC3 | Id::TRUEHD if is_atmos(codec_id, raw_profile) => {
+            format!("{} (Atmos)", name)
+        }
+
+        Id::OPUS => match opus_channel_mapping(extradata(codec_par)) {
+            Some(mapping) => format!("{} ({})", name, mapping),
+            None => name,
+        },
+
+        Id::VORBIS => {
+            match vorbis_channel_order(unsafe { (*codec_par.as_ptr()).ch_layout.nb_channels }) {
+                Some(order) => format!("{} ({})", name, order),
+                None => name,
+            }
+        }
+
         _ => name,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn level_names() {
+        assert_eq!("2.0", av1_level_name(0));
+        assert_eq!("5.1", av1_level_name(13));
+        assert_eq!("Max", av1_level_name(31));
+        assert_eq!("4", vvc_level_name(64));
+        assert_eq!("5.1", vvc_level_name(83));
+        assert_eq!(
+            "Main Level",
+            mpeg2_level_name(Profile::MPEG2(MPEG2::Main), 8)
+        );
+        assert_eq!(
+            "High Level",
+            mpeg2_level_name(Profile::MPEG2(MPEG2::_422), 2)
+        );
+    }
+
+    #[test]
+    fn av1_tiers() {
+        // av1C of a Main profile level 4.0 stream, and of a High tier one.
+        assert_eq!(Some("Main tier"), av1_tier(&[0x81, 0x08, 0x0c, 0x00]));
+        assert_eq!(Some("High tier"), av1_tier(&[0x81, 0x08, 0x8c, 0x00]));
+        assert_eq!(None, av1_tier(&[0x81, 0x08, 0x0c]));
+        assert_eq!(None, av1_tier(&[0x01, 0x08, 0x0c, 0x00]));
+        assert_eq!(None, av1_tier(&[]));
+    }
+
+    #[test]
+    fn profile_names() {
+        // Values from libavcodec/defs.h.
+        assert_eq!("Main Profile", av1_profile_name(0));
+        assert_eq!("Professional Profile", av1_profile_name(2));
+        assert_eq!("Unknown Profile", av1_profile_name(-99));
+        assert_eq!("Main 10 Profile", vvc_profile_name(1));
+        assert_eq!("Main 10 4:4:4 Profile", vvc_profile_name(33));
+        assert_eq!(Some("422 Proxy"), prores_variant(0));
+        assert_eq!(Some("422"), prores_variant(2));
+        assert_eq!(Some("422 HQ"), prores_variant(3));
+        assert_eq!(Some("4444 XQ"), prores_variant(5));
+        assert_eq!(None, prores_variant(-99));
+        assert_eq!(None, dnxhr_name(DNXHD_PROFILE_DNXHD));
+        assert_eq!(Some("Avid DNxHR LB"), dnxhr_name(1));
+        assert_eq!(Some("Avid DNxHR 444"), dnxhr_name(5));
+    }
+
+    #[test]
+    fn audio_profile_names() {
+        // Values from libavcodec/defs.h.
+        assert_eq!(Some("Core"), dts_profile_name(20));
+        assert_eq!(Some("ES"), dts_profile_name(30));
+        assert_eq!(Some("96/24"), dts_profile_name(40));
+        assert_eq!(Some("HD HRA"), dts_profile_name(50));
+        assert_eq!(Some("HD MA"), dts_profile_name(60));
+        assert_eq!(Some("HD MA + DTS:X"), dts_profile_name(61));
+        assert_eq!(Some("HD MA + DTS:X IMAX"), dts_profile_name(62));
+        assert_eq!(Some("Express"), dts_profile_name(70));
+        assert_eq!(None, dts_profile_name(-99));
+        assert!(is_atmos(Id::EAC3, 30));
+        assert!(is_atmos(Id::TRUEHD, 30));
+        assert!(!is_atmos(Id::EAC3, -99));
+        assert!(!is_atmos(Id::AC3, 30));
+    }
+
+    // An Opus identification header with the given channel mapping family.
+    fn opus_head(channels: u8, family: u8) -> Vec<u8> {
+        let mut header = b"OpusHead".to_vec();
+        header.extend([1, channels, 0x38, 0x01, 0x80, 0xbb, 0, 0, 0, 0, family]);
+        header
+    }
+
+    #[test]
+    fn channel_mappings() {
+        assert_eq!(None, opus_channel_mapping(&opus_head(2, 0)));
+        assert_eq!(
+            Some("mapping family 1: Vorbis channel order".to_string()),
+            opus_channel_mapping(&opus_head(6, 1))
+        );
+        assert_eq!(
+            Some("mapping family 2: ambisonics".to_string()),
+            opus_channel_mapping(&opus_head(4, 2))
+        );
+        assert_eq!(
+            Some("mapping family 255: discrete channels".to_string()),
+            opus_channel_mapping(&opus_head(3, 255))
+        );
+        assert_eq!(None, opus_channel_mapping(&opus_head(6, 1)[..18]));
+        assert_eq!(None, opus_channel_mapping(b"OpusTags\x01\x06"));
+        assert_eq!(None, vorbis_channel_order(8));
+        assert_eq!(
+            Some("application-defined channel order"),
+            vorbis_channel_order(9)
+        );
+    }
+}
@@ -0,0 +1,76 @@
+// Programs of multi-program containers, most notably MPEG-TS, where each
+// program (service) groups a number of elementary streams identified by
+// their PIDs.
+
+use crate::ffmpeg::format::context::Input;
+use crate::ffmpeg::DictionaryRef;
+use std::slice;
+
+use crate::tags::{Tags, ToTags};
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ProgramStream {
+    pub index: usize,
+    // Format-specific stream ID, i.e., the PID for MPEG-TS.
+    pub id: i32,
+    pub pid: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ProgramMetadata {
+    pub number: i32,
+    pub service_name: Option<String>,
+    pub service_provider: Option<String>,
+    pub pmt_pid: Option<i32>,
+    pub pcr_pid: Option<i32>,
+    pub streams: Vec<ProgramStream>,
+    pub tags: Tags,
+}
+
+fn format_pid(id: i32) -> String {
+    format!("0x{:x}", id)
+}
+
+// libavformat's AVFormatContext.programs is not exposed by ffmpeg-next, so
+// the raw structs are read directly.
+pub fn parse_programs(input: &Input) -> Vec<ProgramMetadata> {
+    let programs = unsafe {
+        let ctx = &*input.as_ptr();
+        if ctx.nb_programs == 0 || ctx.programs.is_null() {
+            return Vec::new();
+        }
+        slice::from_raw_parts(ctx.programs, ctx.nb_programs as usize)
+    };
+    programs
+        .iter()
+        .map(|&p| {
+            let program = unsafe { &*p };
+            let metadata = unsafe { DictionaryRef::wrap(program.metadata) };
+            let stream_indices = if program.nb_stream_indexes == 0 {
+                &[][..]
+            } else {
+                unsafe {
+                    slice::from_raw_parts(program.stream_index, program.nb_stream_indexes as usize)
+                }
+            };
+            let streams = stream_indices
+                .iter()
+                .filter_map(|&i| input.stream(i as usize))
+                .map(|s| ProgramStream {
+                    index: s.index(),
+                    id: s.id(),
+                    pid: format_pid(s.id()),
+                })
+                .collect();
+            ProgramMetadata {
+                number: program.program_num,
+                service_name: metadata.get("service_name").map(|s| s.to_string()),
+                service_provider: metadata.get("service_provider").map(|s| s.to_string()),
+                pmt_pid: Some(program.pmt_pid).filter(|&pid| pid > 0),
+                pcr_pid: Some(program.pcr_pid).filter(|&pid| pid > 0),
+                streams,
+                tags: metadata.to_tags(),
+            }
+        })
+        .collect()
+}
@@ -1,8 +1,18 @@
-use handlebars::{self, Handlebars};
+use handlebars::{
+    self, Context, Handlebars, Helper, HelperDef, HelperResult, Output, RenderContext,
+};
 use serde::Serialize;
 
+use crate::bitrate::BitRateProfile;
+use crate::continuity::ContinuityReport;
+use crate::detect::DetectedInterval;
+use crate::gop::GopStructure;
 use crate::media_file::MediaFileMetadata;
+use crate::program::ProgramMetadata;
 use crate::stream::{self, StreamMetadata};
+use crate::streamhash::StreamHash;
+use crate::util;
+use crate::verify::IntegrityIssue;
 
 pub trait Render: Serialize {
     fn render(&self, template: &str) -> Result<String, handlebars::TemplateRenderError> {
@@ -21,10 +31,46 @@ pub trait Render: Serialize {
 // additional arguments (a statement I basically pulled out of my ass).
 handlebars_helper!(padkey: |key: str| format!("{:<20}", &[key, ": "].join("")));
 
+// Formats a tag value following padkey, with the layout options of the
+// file. Takes the key as a second parameter, for the column the value
+// starts at.
+struct TagValueHelper {
+    width: Option<usize>,
+    max_length: Option<usize>,
+}
+
+impl HelperDef for TagValueHelper {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        let value = h.param(0).and_then(|p| p.value().as_str()).unwrap_or("");
+        let key = h.param(1).and_then(|p| p.value().as_str()).unwrap_or("");
+        out.write(&util::format_tag_value(
+            value,
+            util::tag_value_column(key),
+            self.width,
+            self.max_length,
+        ))?;
+        Ok(())
+    }
+}
+
 impl Render for MediaFileMetadata {
     fn render(&self, template: &str) -> Result<String, handlebars::TemplateRenderError> {
         let mut handlebars = Handlebars::new();
         handlebars.register_helper("padkey", Box::new(padkey));
+        handlebars.register_helper(
+            "tagvalue",
+            Box::new(TagValueHelper {
+                width: self.options.tag_value_width,
+                max_length: self.options.tag_value_max_length,
+            }),
+        );
         handlebars.render_template(template, &self)
     }
 
@@ -35,10 +81,27 @@ impl Render for MediaFileMetadata {
          {{/if}}\
          Filename:               {{{file_name}}}\n\
          File size:              {{{file_size}}} ({{{file_size_base10}}}, {{{file_size_base2}}})\n\
-         {{#if options.include_checksum and hash }}\
-         SHA-256 digest:         {{{hash}}}\n\
+         {{#if options.include_checksum}}\
+         {{#if checksums.md5}}\
+         MD5 digest:             {{{checksums.md5}}}\n\
+         {{/if}}\
+         {{#if checksums.sha1}}\
+         SHA-1 digest:           {{{checksums.sha1}}}\n\
+         {{/if}}\
+         {{#if checksums.sha256}}\
+         SHA-256 digest:         {{{checksums.sha256}}}\n\
+         {{/if}}\
+         {{#if checksums.blake3}}\
+         BLAKE3 digest:          {{{checksums.blake3}}}\n\
+         {{/if}}\
          {{/if}}\
          Container format:       {{{container_format}}}\n\
+         {{#if fast_start}}\
+         Fast start:             {{{fast_start}}}\n\
+         {{/if}}\
+         {{#if fragmented}}\
+         Fragmented:             {{{fragmented}}}\n\
+         {{/if}}\
          Duration:               {{#if duration}}{{{duration}}}{{else}}Not available{{/if}}\n\
          {{#if pixel_dimensions}}\
          Pixel dimensions:       {{{pixel_dimensions}}}\n\
@@ -49,6 +112,10 @@ impl Render for MediaFileMetadata {
          {{#if display_aspect_ratio}}\
          Display aspect ratio:   {{{display_aspect_ratio}}}\n\
          {{/if}}\
+         {{#if options.include_crop_detection}}{{#if crop_area}}\
+           Active picture area:    {{{crop_area.geometry}}}\n\
+           Effective aspect ratio: {{{effective_aspect_ratio}}}\n\
+         {{/if}}{{/if}}\
          {{#if scan_type}}\
          Scan type:              {{{scan_type}}}\n\
          {{/if}}\
@@ -56,27 +123,72 @@ impl Render for MediaFileMetadata {
          Frame rate:             {{{frame_rate}}}\n\
          {{/if}}\
          Bit rate:               {{{bit_rate}}}\n\
+         {{#if options.include_integrity_check}}\
+           Integrity:              {{#if integrity_issues_rendered}}Damaged{{else}}OK{{/if}}\n\
+           {{#each integrity_issues_rendered as |issue|}}    {{{issue}}}\n{{/each}}\
+         {{/if}}\
          Streams:\n\
          {{#each streams_metadata_rendered as |stream_metadata|}}    {{{stream_metadata}}}\n{{/each}}\
+         {{#if programs_rendered}}\
+           Programs:\n\
+           {{#each programs_rendered as |program|}}    {{{program}}}\n{{/each}}\
+         {{/if}}\
+         {{#if matroska}}\
+           Matroska:\n\
+           {{#if matroska.muxing_app}}    Muxing app:         {{{matroska.muxing_app}}}\n{{/if}}\
+           {{#if matroska.writing_app}}    Writing app:        {{{matroska.writing_app}}}\n{{/if}}\
+           {{#if matroska.segment_uid}}    Segment UID:        {{{matroska.segment_uid}}}\n{{/if}}\
+           {{#if matroska.date}}    Date:               {{{matroska.date}}}\n{{/if}}\
+           {{#if matroska.has_cues}}    Cues:               yes\n{{else}}    Cues:               no\n{{/if}}\
+         {{/if}}\
+         {{#if music}}\
+           Music:\n\
+           {{#if music.track_gain}}    Track gain:         {{{music.track_gain}}}\n{{/if}}\
+           {{#if music.track_peak}}    Track peak:         {{{music.track_peak}}}\n{{/if}}\
+           {{#if music.album_gain}}    Album gain:         {{{music.album_gain}}}\n{{/if}}\
+           {{#if music.album_peak}}    Album peak:         {{{music.album_peak}}}\n{{/if}}\
+           {{#if music.reference_loudness}}    Reference level:    {{{music.reference_loudness}}}\n{{/if}}\
+           {{#each music.musicbrainz_ids as |mbid|}}    {{padkey mbid.label}}{{{mbid.id}}}\n{{/each}}\
+         {{/if}}\
+         {{#if options.include_gop_analysis}}{{#if gop_structures_rendered}}\
+           GOP structure:\n\
+           {{#each gop_structures_rendered as |gop_structure|}}    {{{gop_structure}}}\n{{/each}}\
+         {{/if}}{{/if}}\
+         {{#if options.include_bit_rate_profile}}{{#if bit_rate_profiles_rendered}}\
+           Bit rate profile:\n\
+           {{#each bit_rate_profiles_rendered as |bit_rate_profile|}}    {{{bit_rate_profile}}}\n{{/each}}\
+         {{/if}}{{/if}}\
+         {{#if options.include_interval_detection}}{{#if detected_intervals_rendered}}\
+           Detected intervals:\n\
+           {{#each detected_intervals_rendered as |interval|}}    {{{interval}}}\n{{/each}}\
+         {{/if}}{{/if}}\
+         {{#if options.include_continuity_analysis}}\
+           Timestamp continuity:\n\
+           {{#each continuity_reports_rendered as |report|}}    {{{report}}}\n{{else}}    No problems found\n{{/each}}\
+         {{/if}}\
+         {{#if options.include_stream_hashes}}{{#if stream_hashes_rendered}}\
+           Stream hashes:\n\
+           {{#each stream_hashes_rendered as |stream_hash|}}    {{{stream_hash}}}\n{{/each}}\
+         {{/if}}{{/if}}\
          \
          {{#if options.include_all_tags}}\
            {{#if tags}}\
              Tags:\n\
-             {{#each tags as |kv|}}    {{padkey kv.0}}{{{kv.1}}}\n{{/each}}\
+             {{#each tags as |kv|}}    {{padkey kv.0}}{{tagvalue kv.1 kv.0}}\n{{/each}}\
            {{/if}}\
            {{#each streams_tags as |s|}}\
              {{#if s.tags}}  #{{{s.index}}}\n\
-             {{#each s.tags as |kv|}}    {{padkey kv.0}}{{{kv.1}}}\n{{/each}}\
+             {{#each s.tags as |kv|}}    {{padkey kv.0}}{{tagvalue kv.1 kv.0}}\n{{/each}}\
              {{/if}}\
            {{/each}}\
          {{else}}{{#if options.include_tags}}\
            {{#if filtered_tags}}\
              Tags:\n\
-             {{#each filtered_tags as |kv|}}    {{padkey kv.0}}{{{kv.1}}}\n{{/each}}\
+             {{#each filtered_tags as |kv|}}    {{padkey kv.0}}{{tagvalue kv.1 kv.0}}\n{{/each}}\
            {{/if}}\
            {{#each streams_filtered_tags as |s|}}\
              {{#if s.tags}}  #{{{s.index}}}\n\
-             {{#each s.tags as |kv|}}    {{padkey kv.0}}{{{kv.1}}}\n{{/each}}\
+             {{#each s.tags as |kv|}}    {{padkey kv.0}}{{tagvalue kv.1 kv.0}}\n{{/each}}\
              {{/if}}\
            {{/each}}\
          {{/if}}{{/if}}\
@@ -85,6 +197,92 @@ impl Render for MediaFileMetadata {
     }
 }
 
+// GopStructure renders to a line of statistics, followed by the positions
+// of the first few keyframes on an indented line.
+impl Render for GopStructure {
+    fn default_template() -> String {
+        "#{{{index}}}: {{{frame_count}}} frames, {{{gop_count}}} keyframes\
+         {{#if min_frames}}\
+         , GOP length {{{min_frames}}}-{{{max_frames}}} frames (avg {{{average_frames}}})\
+         {{/if}}\
+         {{#if min_duration}}\
+         , {{{min_duration}}}-{{{max_duration}}} (avg {{{average_duration}}})\
+         {{/if}}\
+         {{#if closed_gops}}, closed GOPs{{/if}}\
+         {{#if (eq closed_gops false)}}, open GOPs{{/if}}\
+         {{#if b_frames}}, B-frames{{else}}, no B-frames{{/if}}\
+         {{#if first_keyframes}}\
+         \n        First keyframes: \
+         {{#each first_keyframes as |k|}}\
+         {{#if @index}}, {{/if}}{{{k.frame}}}{{#if k.time}} ({{{k.time}}}){{/if}}\
+         {{/each}}\
+         {{/if}}\
+         "
+        .to_string()
+    }
+}
+
+impl Render for BitRateProfile {
+    fn default_template() -> String {
+        "#{{{index}}} ({{{window}}} windows): \
+         {{#if peak}}\
+         peak {{{peak}}} at {{{peak_time}}}\
+         , min {{{minimum}}}\
+         {{#each percentiles as |p|}}, P{{{p.percentile}}} {{{p.bit_rate}}}{{/each}}\
+         {{else}}\
+         no packets\
+         {{/if}}\
+         "
+        .to_string()
+    }
+}
+
+impl Render for DetectedInterval {
+    fn default_template() -> String {
+        "#{{{index}}}: {{{kind}}} ({{{position}}}), {{{start}}} - {{{end}}} ({{{duration}}})"
+            .to_string()
+    }
+}
+
+impl Render for ProgramMetadata {
+    fn default_template() -> String {
+        "Program {{{number}}}\
+         {{#if service_name}} ({{{service_name}}}{{#if service_provider}}, {{{service_provider}}}{{/if}}){{/if}}: \
+         {{#if streams}}\
+           {{#each streams as |s|}}{{#if @index}}, {{/if}}#{{{s.index}}} (PID {{{s.pid}}}){{/each}}\
+         {{else}}\
+           no streams\
+         {{/if}}"
+            .to_string()
+    }
+}
+
+impl Render for ContinuityReport {
+    fn default_template() -> String {
+        "{{{location}}}: {{{summary}}}\
+         {{#each events as |e|}}\
+           \n        {{{e.time}}}: {{{e.description}}}{{#if e.delta}} ({{{e.delta}}}){{/if}}\
+         {{/each}}"
+            .to_string()
+    }
+}
+
+// StreamHash renders to the packet digest, followed by the frame digest,
+// if any, on an indented line.
+impl Render for StreamHash {
+    fn default_template() -> String {
+        "#{{{index}}}: {{{algorithm}}} {{{packets}}} ({{{packet_count}}} packets)\
+         {{#if frames}}\n        {{{algorithm}}} {{{frames}}} ({{{frame_count}}} frames){{/if}}"
+            .to_string()
+    }
+}
+
+impl Render for IntegrityIssue {
+    fn default_template() -> String {
+        "{{{location}}}: {{{description}}}{{#if first}}, first at {{{first}}}{{/if}}".to_string()
+    }
+}
+
 // StreamMetadata renders to a one-line string similar to avcodec_string
 // (libavcodec/utils.c), which is used by ffmpeg/ffprobe's to display stream
 // info.
@@ -108,6 +306,7 @@ impl Render for stream::VideoMetadata {
     fn default_template() -> String {
         "#{{{index}}}: Video\
          , {{{codec_desc}}}\
+         {{#if options.verbose}}{{#if codec_tag}} [{{{codec_tag}}}]{{/if}}{{/if}}\
          {{#if pixel_fmt}}\
          , {{{pixel_fmt}}}{{#if color_spec_str}} ({{{color_spec_str}}}){{/if}}\
          {{/if}}\
@@ -119,6 +318,9 @@ impl Render for stream::VideoMetadata {
          {{#if bit_rate}}\
          , {{{bit_rate}}}\
          {{/if}}\
+         {{#if closed_captions}}\
+         , with CC\
+         {{/if}}\
          "
         .to_string()
     }
@@ -129,11 +331,17 @@ impl Render for stream::AudioMetadata {
         "#{{{index}}}: Audio \
          ({{#if language}}{{{language}}}{{else}}und{{/if}})\
          , {{{codec_desc}}}\
+         {{#if options.verbose}}{{#if codec_tag}} [{{{codec_tag}}}]{{/if}}{{/if}}\
          , {{{sample_rate}}}\
          , {{{channel_layout}}}\
          {{#if bit_rate}}\
          , {{{bit_rate}}}\
          {{/if}}\
+         {{#if loudness}}\
+         {{#if loudness.integrated}}, {{{loudness.integrated}}}{{/if}}\
+         {{#if loudness.range}}, LRA {{{loudness.range}}}{{/if}}\
+         {{#if loudness.true_peak}}, peak {{{loudness.sample_peak}}} ({{{loudness.true_peak}}}){{/if}}\
+         {{/if}}\
          "
         .to_string()
     }
@@ -144,6 +352,18 @@ impl Render for stream::SubtitleMetadata {
         "#{{{index}}}: Subtitle \
          ({{#if language}}{{{language}}}{{else}}und{{/if}})\
          , {{{codec_desc}}}\
+         {{#if options.verbose}}{{#if codec_tag}} [{{{codec_tag}}}]{{/if}}{{/if}}\
+         {{#if kind}}\
+         , {{{kind}}}\
+         {{/if}}\
+         {{#if canvas_dimensions}}\
+         , {{{canvas_dimensions}}}\
+         {{/if}}\
+         {{#if events}}\
+         , {{{events.event_count}}} event{{#if (ne events.event_count 1)}}s{{/if}}\
+         {{#if events.first_event}} ({{{events.first_event}}} - {{{events.last_event}}}){{/if}}\
+         {{#if events.encoding}}, {{{events.encoding}}}{{/if}}\
+         {{/if}}\
          "
         .to_string()
     }
@@ -7,7 +7,10 @@ use libc;
 use std::io;
 use std::str::from_utf8_unchecked;
 
+use crate::loudness::Loudness;
 use crate::prejudice;
+use crate::stream;
+use crate::streamhash::StreamHash;
 
 #[derive(Clone, Debug, Serialize)]
 pub struct AudioMetadata {
@@ -18,6 +21,9 @@ pub struct AudioMetadata {
     #[serde(skip_serializing)]
     pub _codec: codec::Id,
     pub codec_desc: String,
+    #[serde(skip_serializing)]
+    pub _codec_tag: u32,
+    pub codec_tag: Option<String>,
 
     #[serde(skip_serializing)]
     pub _sample_rate: u32,
@@ -30,6 +36,14 @@ pub struct AudioMetadata {
     #[serde(skip_serializing)]
     pub _bit_rate: Option<u64>,
     pub bit_rate: Option<String>,
+
+    // Only measured on request, as it requires decoding the entire stream.
+    pub loudness: Option<Loudness>,
+
+    // Only computed on request, as it requires reading all packets.
+    pub content_hash: Option<StreamHash>,
+
+    pub options: stream::StreamMetadataOptions,
 }
 
 impl AudioMetadata {
@@ -48,6 +62,7 @@ impl AudioMetadata {
 
         let _codec = codec_par.id();
         let codec_desc = prejudice::codec_description(&codec_par);
+        let (_codec_tag, codec_tag) = stream::codec_tag(codec_par);
 
         let _sample_rate = audio.rate();
         let sample_rate = format!("{} Hz", _sample_rate);
@@ -65,12 +80,17 @@ impl AudioMetadata {
             language,
             _codec,
             codec_desc,
+            _codec_tag,
+            codec_tag,
             _sample_rate,
             sample_rate,
             _channel_layout,
             channel_layout,
             _bit_rate,
             bit_rate,
+            loudness: None,
+            content_hash: None,
+            options: stream::StreamMetadataOptions::default(),
         })
     }
 
@@ -1,8 +1,12 @@
 use crate::ffmpeg::codec::context::Context;
+use crate::ffmpeg::codec::Parameters;
 use crate::ffmpeg::media::Type;
 use crate::ffmpeg::Stream;
 use std::io;
 
+use crate::streamhash::StreamHash;
+use crate::util;
+
 pub mod video;
 pub use self::video::VideoMetadata;
 
@@ -22,9 +26,18 @@ pub enum StreamMetadata {
     UnknownMetadata(UnknownMetadata),
 }
 
+// Rendering options shared by the video, audio and subtitle streams.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct StreamMetadataOptions {
+    pub verbose: bool,
+}
+
 #[derive(Clone, Debug, Serialize)]
 pub struct DataMetadata {
     pub index: usize,
+
+    // Only computed on request, as it requires reading all packets.
+    pub content_hash: Option<StreamHash>,
 }
 
 #[derive(Clone, Debug, Serialize)]
@@ -49,6 +62,37 @@ impl StreamMetadata {
         }
     }
 
+    // Attachments and unknown streams have no packets to hash.
+    pub fn content_hash(&self) -> Option<&StreamHash> {
+        match self {
+            StreamMetadata::VideoMetadata(m) => m.content_hash.as_ref(),
+            StreamMetadata::AudioMetadata(m) => m.content_hash.as_ref(),
+            StreamMetadata::SubtitleMetadata(m) => m.content_hash.as_ref(),
+            StreamMetadata::DataMetadata(m) => m.content_hash.as_ref(),
+            _ => None,
+        }
+    }
+
+    pub fn set_content_hash(&mut self, content_hash: Option<StreamHash>) {
+        match self {
+            StreamMetadata::VideoMetadata(m) => m.content_hash = content_hash,
+            StreamMetadata::AudioMetadata(m) => m.content_hash = content_hash,
+            StreamMetadata::SubtitleMetadata(m) => m.content_hash = content_hash,
+            StreamMetadata::DataMetadata(m) => m.content_hash = content_hash,
+            _ => (),
+        }
+    }
+
+    // Streams without a verbose template ignore this.
+    pub fn set_verbose(&mut self, on: bool) {
+        match self {
+            StreamMetadata::VideoMetadata(m) => m.options.verbose = on,
+            StreamMetadata::AudioMetadata(m) => m.options.verbose = on,
+            StreamMetadata::SubtitleMetadata(m) => m.options.verbose = on,
+            _ => (),
+        }
+    }
+
     pub fn video_metadata(&self) -> Option<VideoMetadata> {
         match self {
             StreamMetadata::VideoMetadata(m) => Some(m.clone()),
@@ -71,6 +115,12 @@ impl StreamMetadata {
     }
 }
 
+// The codec tag (fourcc) of the stream, if set by the container.
+pub fn codec_tag(codec_par: &Parameters) -> (u32, Option<String>) {
+    let tag = unsafe { (*codec_par.as_ptr()).codec_tag };
+    (tag, Some(tag).filter(|&t| t != 0).map(util::fourcc))
+}
+
 pub fn parse_stream_meatadata(stream: Stream) -> io::Result<StreamMetadata> {
     let index = stream.index();
     let codec_ctx = Context::from_parameters(stream.parameters())?;
@@ -86,7 +136,10 @@ pub fn parse_stream_meatadata(stream: Stream) -> io::Result<StreamMetadata> {
         Type::Subtitle => {
             StreamMetadata::SubtitleMetadata(SubtitleMetadata::new(index, &codec_par, &tags)?)
         }
-        Type::Data => StreamMetadata::DataMetadata(DataMetadata { index }),
+        Type::Data => StreamMetadata::DataMetadata(DataMetadata {
+            index,
+            content_hash: None,
+        }),
         Type::Attachment => StreamMetadata::AttachmentMetadata(AttachmentMetadata { index }),
         Type::Unknown => StreamMetadata::UnknownMetadata(UnknownMetadata { index }),
     })
@@ -1,8 +1,19 @@
 use crate::ffmpeg::codec::{self, Parameters};
+use crate::ffmpeg::ffi;
 use crate::ffmpeg::DictionaryRef;
 use std::io;
 
 use crate::prejudice;
+use crate::stream;
+use crate::streamhash::StreamHash;
+use crate::subtitles::SubtitleEvents;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SubtitleKind {
+    Text,
+    Bitmap,
+}
 
 #[derive(Clone, Debug, Serialize)]
 pub struct SubtitleMetadata {
@@ -13,6 +24,27 @@ pub struct SubtitleMetadata {
     #[serde(skip_serializing)]
     pub _codec: codec::Id,
     pub codec_desc: String,
+    #[serde(skip_serializing)]
+    pub _codec_tag: u32,
+    pub codec_tag: Option<String>,
+
+    // As declared by the codec descriptor; unknown for some codecs.
+    #[serde(skip_serializing)]
+    pub _kind: Option<SubtitleKind>,
+    pub kind: Option<String>,
+
+    // Only available for bitmap subtitles, e.g., PGS and VobSub.
+    pub canvas_width: Option<u32>,
+    pub canvas_height: Option<u32>,
+    pub canvas_dimensions: Option<String>,
+
+    // Only counted on request, as it requires demuxing the entire file.
+    pub events: Option<SubtitleEvents>,
+
+    // Only computed on request, as it requires reading all packets.
+    pub content_hash: Option<StreamHash>,
+
+    pub options: stream::StreamMetadataOptions,
 }
 
 impl SubtitleMetadata {
@@ -28,12 +60,58 @@ impl SubtitleMetadata {
 
         let _codec = codec_par.id();
         let codec_desc = prejudice::codec_description(&codec_par);
+        let (_codec_tag, codec_tag) = stream::codec_tag(codec_par);
+
+        let props = unsafe {
+            let descriptor = ffi::avcodec_descriptor_get(_codec.into());
+            if descriptor.is_null() {
+                0
+            } else {
+                (*descriptor).props
+            }
+        };
+        let _kind = if props & ffi::AV_CODEC_PROP_TEXT_SUB as i32 != 0 {
+            Some(SubtitleKind::Text)
+        } else if props & ffi::AV_CODEC_PROP_BITMAP_SUB as i32 != 0 {
+            Some(SubtitleKind::Bitmap)
+        } else {
+            None
+        };
+        let kind = _kind.map(|k| match k {
+            SubtitleKind::Text => "text".to_string(),
+            SubtitleKind::Bitmap => "bitmap".to_string(),
+        });
+
+        let (width, height) = unsafe {
+            let par = &*codec_par.as_ptr();
+            (par.width, par.height)
+        };
+        let (canvas_width, canvas_height, canvas_dimensions) =
+            if _kind == Some(SubtitleKind::Bitmap) && width > 0 && height > 0 {
+                (
+                    Some(width as u32),
+                    Some(height as u32),
+                    Some(format!("{}x{}", width, height)),
+                )
+            } else {
+                (None, None, None)
+            };
 
         Ok(SubtitleMetadata {
             index,
             language,
             _codec,
             codec_desc,
+            _codec_tag,
+            codec_tag,
+            _kind,
+            kind,
+            canvas_width,
+            canvas_height,
+            canvas_dimensions,
+            events: None,
+            content_hash: None,
+            options: stream::StreamMetadataOptions::default(),
         })
     }
 }
@@ -6,6 +6,8 @@ use crate::ffmpeg::Stream;
 use std::io;
 
 use crate::prejudice;
+use crate::stream;
+use crate::streamhash::StreamHash;
 
 #[derive(Clone, Debug, Serialize)]
 pub struct VideoMetadata {
@@ -15,6 +17,9 @@ pub struct VideoMetadata {
     pub _codec: codec::Id,
     pub codec_desc: String,
     #[serde(skip_serializing)]
+    pub _codec_tag: u32,
+    pub codec_tag: Option<String>,
+    #[serde(skip_serializing)]
     pub _pixel_fmt: Pixel,
     pub pixel_fmt: Option<String>,
     #[serde(skip_serializing)]
@@ -48,6 +53,14 @@ pub struct VideoMetadata {
     #[serde(skip_serializing)]
     pub _bit_rate: Option<u64>,
     pub bit_rate: Option<String>,
+
+    // Only detected on request, as it requires decoding frames.
+    pub closed_captions: Option<bool>,
+
+    // Only computed on request, as it requires reading all packets.
+    pub content_hash: Option<StreamHash>,
+
+    pub options: stream::StreamMetadataOptions,
 }
 
 impl VideoMetadata {
@@ -61,6 +74,7 @@ impl VideoMetadata {
 
         let _codec = codec_par.id();
         let codec_desc = prejudice::codec_description(&codec_par);
+        let (_codec_tag, codec_tag) = stream::codec_tag(codec_par);
 
         let _pixel_fmt = video.format();
         let pixel_fmt = _pixel_fmt.descriptor().map(|d| d.name().to_string());
@@ -141,6 +155,8 @@ impl VideoMetadata {
             index,
             _codec,
             codec_desc,
+            _codec_tag,
+            codec_tag,
             _pixel_fmt,
             pixel_fmt,
             _color_range,
@@ -163,6 +179,9 @@ impl VideoMetadata {
             frame_rate,
             _bit_rate,
             bit_rate,
+            closed_captions: None,
+            content_hash: None,
+            options: stream::StreamMetadataOptions::default(),
         })
     }
 }
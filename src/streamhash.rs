@@ -0,0 +1,223 @@
+// Per-stream content hashes, which unlike file checksums survive
+// remuxing: over demuxed packet payloads, as computed by ffmpeg's
+// streamhash muxer, and optionally over decoded frames. Packet payloads
+// may still differ for identical content when the bitstream format
+// changes with the container, e.g., H.264 in MPEG-TS (Annex B) vs MP4
+// (length-prefixed NAL units); decoded frames do not.
+
+use crate::ffmpeg::ffi;
+use crate::ffmpeg::format::context::Input;
+use crate::ffmpeg::frame;
+use crate::ffmpeg::media::Type;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io;
+
+use crate::decode;
+use crate::util::{ChecksumAlgorithm, Hasher};
+
+pub const STREAM_HASH_ALGORITHM: ChecksumAlgorithm = ChecksumAlgorithm::Sha256;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct StreamHash {
+    pub index: usize,
+    pub algorithm: String,
+    pub packet_count: u64,
+    // Hex digest of packet payloads.
+    pub packets: String,
+    // Only for audio and video streams, on request, as it requires decoding
+    // the entire stream.
+    pub frame_count: Option<u64>,
+    pub frames: Option<String>,
+}
+
+impl StreamHash {
+    // Whether two streams, possibly of different files, carry the same
+    // content: decoded frames are compared if both are hashed, otherwise
+    // packets.
+    pub fn same_content(&self, other: &StreamHash) -> bool {
+        if self.algorithm != other.algorithm {
+            return false;
+        }
+        match (&self.frames, &other.frames) {
+            (Some(a), Some(b)) => a == b && self.frame_count == other.frame_count,
+            _ => self.packets == other.packets && self.packet_count == other.packet_count,
+        }
+    }
+}
+
+struct Counter {
+    count: u64,
+    hasher: Hasher,
+}
+
+impl Counter {
+    fn new() -> Counter {
+        Counter {
+            count: 0,
+            hasher: Hasher::new(STREAM_HASH_ALGORITHM),
+        }
+    }
+}
+
+// Pictures are hashed as tightly packed planes, without line padding.
+fn hash_video_frame(hasher: &mut Hasher, frame: &frame::Video) {
+    let format: ffi::AVPixelFormat = frame.format().into();
+    let (width, height) = (frame.width() as i32, frame.height() as i32);
+    unsafe {
+        let size = ffi::av_image_get_buffer_size(format, width, height, 1);
+        if size < 0 {
+            debug!("cannot hash {:?} frame", frame.format());
+            return;
+        }
+        let mut buffer = vec![0u8; size as usize];
+        let ptr = frame.as_ptr();
+        let copied = ffi::av_image_copy_to_buffer(
+            buffer.as_mut_ptr(),
+            size,
+            (*ptr).data.as_ptr() as *const *const u8,
+            (*ptr).linesize.as_ptr(),
+            format,
+            width,
+            height,
+            1,
+        );
+        if copied >= 0 {
+            hasher.update(&buffer[..copied as usize]);
+        }
+    }
+}
+
+// Samples are hashed plane by plane, without padding.
+fn hash_audio_frame(hasher: &mut Hasher, frame: &frame::Audio) {
+    for plane in decode::audio_planes(frame) {
+        hasher.update(plane);
+    }
+}
+
+fn indices_of(input: &Input, medium: Type) -> Vec<usize> {
+    input
+        .streams()
+        .filter(|s| s.parameters().medium() == medium)
+        .map(|s| s.index())
+        .collect()
+}
+
+// Hashes the packet payloads of each stream with any packets, and if
+// decode_frames is set, the decoded frames of audio and video streams, all
+// in a single pass over the input.
+pub fn hash_streams(input: &mut Input, decode_frames: bool) -> io::Result<Vec<StreamHash>> {
+    let (audio_indices, video_indices) = if decode_frames {
+        (
+            indices_of(input, Type::Audio),
+            indices_of(input, Type::Video),
+        )
+    } else {
+        (Vec::new(), Vec::new())
+    };
+    let mut packet_counters: HashMap<usize, Counter> = HashMap::new();
+    // Shared by the audio and video frame callbacks.
+    let frame_counters: RefCell<HashMap<usize, Counter>> = RefCell::new(HashMap::new());
+    decode::decode_packets(
+        input,
+        &audio_indices,
+        &video_indices,
+        |index, packet| {
+            let counter = packet_counters.entry(index).or_insert_with(Counter::new);
+            counter.count += 1;
+            if let Some(data) = packet.data() {
+                counter.hasher.update(data);
+            }
+        },
+        |index, frame| {
+            let mut counters = frame_counters.borrow_mut();
+            let counter = counters.entry(index).or_insert_with(Counter::new);
+            counter.count += 1;
+            hash_audio_frame(&mut counter.hasher, frame);
+        },
+        |index, frame| {
+            let mut counters = frame_counters.borrow_mut();
+            let counter = counters.entry(index).or_insert_with(Counter::new);
+            counter.count += 1;
+            hash_video_frame(&mut counter.hasher, frame);
+        },
+    )?;
+    let mut frame_counters = frame_counters.into_inner();
+
+    let mut hashes = packet_counters
+        .into_iter()
+        .map(|(index, counter)| {
+            let frames = frame_counters.remove(&index);
+            StreamHash {
+                index,
+                algorithm: STREAM_HASH_ALGORITHM.name().to_string(),
+                packet_count: counter.count,
+                packets: counter.hasher.finalize(),
+                frame_count: frames.as_ref().map(|c| c.count),
+                frames: frames.map(|c| c.hasher.finalize()),
+            }
+        })
+        .collect::<Vec<_>>();
+    hashes.sort_by_key(|h| h.index);
+    Ok(hashes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ffmpeg::format::sample::Type as SampleType;
+    use crate::ffmpeg::format::Sample;
+    use crate::ffmpeg::util::channel_layout::ChannelLayout;
+
+    fn stream_hash(packets: &str, frames: Option<&str>) -> StreamHash {
+        StreamHash {
+            index: 0,
+            algorithm: "SHA-256".to_string(),
+            packet_count: 10,
+            packets: packets.to_string(),
+            frame_count: frames.map(|_| 10),
+            frames: frames.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn compare_content() {
+        let a = stream_hash("aaaa", Some("ffff"));
+        assert!(a.same_content(&stream_hash("aaaa", None)));
+        assert!(a.same_content(&stream_hash("bbbb", Some("ffff"))));
+        assert!(!a.same_content(&stream_hash("aaaa", Some("eeee"))));
+        assert!(!stream_hash("aaaa", None).same_content(&stream_hash("bbbb", None)));
+    }
+
+    // A stereo planar float frame with the given sample values per channel.
+    fn planar_frame(values: [f32; 2]) -> frame::Audio {
+        let samples = 4;
+        let mut frame = frame::Audio::new(
+            Sample::F32(SampleType::Planar),
+            samples,
+            ChannelLayout::default(2),
+        );
+        for (ch, &value) in values.iter().enumerate() {
+            unsafe {
+                let plane = *(*frame.as_mut_ptr()).extended_data.add(ch) as *mut f32;
+                for i in 0..samples {
+                    *plane.add(i) = value;
+                }
+            }
+        }
+        frame
+    }
+
+    fn audio_frame_digest(frame: &frame::Audio) -> String {
+        let mut hasher = Hasher::new(STREAM_HASH_ALGORITHM);
+        hash_audio_frame(&mut hasher, frame);
+        hasher.finalize()
+    }
+
+    #[test]
+    fn planar_audio_hashes_all_channels() {
+        let a = audio_frame_digest(&planar_frame([0.5, 0.25]));
+        assert_eq!(a, audio_frame_digest(&planar_frame([0.5, 0.25])));
+        assert_ne!(a, audio_frame_digest(&planar_frame([0.5, -0.25])));
+    }
+}
@@ -0,0 +1,139 @@
+// Subtitle event statistics, gathered by demuxing subtitle streams.
+
+use crate::ffmpeg::format::context::Input;
+use crate::ffmpeg::media::Type;
+use std::collections::HashMap;
+use std::io;
+use std::str;
+
+use crate::util;
+
+// At most this many bytes of each text subtitle stream are examined to
+// guess the character encoding.
+const MAX_ENCODING_SAMPLE: usize = 1 << 20;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SubtitleEvents {
+    pub event_count: u64,
+    // Start of the first event and end of the last event.
+    #[serde(skip_serializing)]
+    pub _first_event: Option<f64>,
+    pub first_event: Option<String>,
+    #[serde(skip_serializing)]
+    pub _last_event: Option<f64>,
+    pub last_event: Option<String>,
+    // Only guessed for text subtitles.
+    pub encoding: Option<String>,
+}
+
+// Guesses the character encoding of text subtitle data.
+pub fn guess_encoding(data: &[u8]) -> &'static str {
+    if data.starts_with(&[0xef, 0xbb, 0xbf]) {
+        "UTF-8 (BOM)"
+    } else if data.starts_with(&[0xff, 0xfe]) {
+        "UTF-16LE"
+    } else if data.starts_with(&[0xfe, 0xff]) {
+        "UTF-16BE"
+    } else if data.is_ascii() {
+        "ASCII"
+    } else if str::from_utf8(data).is_ok() {
+        "UTF-8"
+    } else {
+        "8-bit, not UTF-8"
+    }
+}
+
+struct EventTally {
+    time_base: f64,
+    text: bool,
+    event_count: u64,
+    first: Option<f64>,
+    last: Option<f64>,
+    sample: Vec<u8>,
+}
+
+// Demuxes all subtitle streams of the input and counts their events. text
+// tells whether the subtitle stream with the given index is text-based.
+pub fn analyze_subtitles<F>(
+    input: &mut Input,
+    text: F,
+) -> io::Result<HashMap<usize, SubtitleEvents>>
+where
+    F: Fn(usize) -> bool,
+{
+    let mut tallies: HashMap<usize, EventTally> = HashMap::new();
+    for stream in input.streams() {
+        if stream.parameters().medium() == Type::Subtitle {
+            tallies.insert(
+                stream.index(),
+                EventTally {
+                    time_base: f64::from(stream.time_base()),
+                    text: text(stream.index()),
+                    event_count: 0,
+                    first: None,
+                    last: None,
+                    sample: Vec::new(),
+                },
+            );
+        }
+    }
+    if tallies.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let start = match unsafe { (*input.as_ptr()).start_time } {
+        crate::ffmpeg::ffi::AV_NOPTS_VALUE => 0f64,
+        t => t as f64 / crate::ffmpeg::ffi::AV_TIME_BASE as f64,
+    };
+    for (stream, packet) in input.packets() {
+        if let Some(tally) = tallies.get_mut(&stream.index()) {
+            tally.event_count += 1;
+            if let Some(pts) = packet.pts() {
+                let event_start = pts as f64 * tally.time_base - start;
+                let event_end = event_start + packet.duration().max(0) as f64 * tally.time_base;
+                tally.first = Some(tally.first.map_or(event_start, |t| t.min(event_start)));
+                tally.last = Some(tally.last.map_or(event_end, |t| t.max(event_end)));
+            }
+            if tally.text && tally.sample.len() < MAX_ENCODING_SAMPLE {
+                if let Some(data) = packet.data() {
+                    tally.sample.extend_from_slice(data);
+                }
+            }
+        }
+    }
+
+    Ok(tallies
+        .into_iter()
+        .map(|(index, tally)| {
+            let encoding = if tally.text && !tally.sample.is_empty() {
+                Some(guess_encoding(&tally.sample).to_string())
+            } else {
+                None
+            };
+            (
+                index,
+                SubtitleEvents {
+                    event_count: tally.event_count,
+                    _first_event: tally.first,
+                    first_event: tally.first.map(util::format_seconds),
+                    _last_event: tally.last,
+                    last_event: tally.last.map(util::format_seconds),
+                    encoding,
+                },
+            )
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encoding_guesses() {
+        assert_eq!("ASCII", guess_encoding(b"Hello, world!"));
+        assert_eq!("UTF-8", guess_encoding("Grüß Gott".as_bytes()));
+        assert_eq!("UTF-8 (BOM)", guess_encoding(b"\xef\xbb\xbfHello"));
+        assert_eq!("8-bit, not UTF-8", guess_encoding(b"Gr\xfc\xdf Gott"));
+    }
+}
@@ -1,27 +1,325 @@
 use crate::ffmpeg::DictionaryRef;
 use regex::Regex;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::lyrics;
 
 pub type Tags = Vec<(String, String)>;
 
+// Container families with their own tagging conventions. libavformat
+// converts most well-known keys to generic ones (title, artist, track,
+// etc.), but not all, and not consistently.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TagFamily {
+    Id3,
+    VorbisComment,
+    Mp4,
+    Matroska,
+    Other,
+}
+
+impl TagFamily {
+    pub fn from_format_name(name: &str) -> TagFamily {
+        match name {
+            "mp3" | "aiff" | "wav" | "aac" => TagFamily::Id3,
+            "ogg" | "flac" => TagFamily::VorbisComment,
+            "mov,mp4,m4a,3gp,3g2,mj2" => TagFamily::Mp4,
+            "matroska,webm" => TagFamily::Matroska,
+            _ => TagFamily::Other,
+        }
+    }
+
+    // Keys specific to the family, matched case-insensitively after the
+    // generic keys.
+    fn keys(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            TagFamily::Id3 => &[
+                ("TIT2", "title"),
+                ("TPE1", "artist"),
+                ("TALB", "album"),
+                ("TPE2", "album_artist"),
+                ("TRCK", "track"),
+                ("TPOS", "disc"),
+                ("TDRC", "date"),
+                ("TYER", "date"),
+                ("TCON", "genre"),
+                ("COMM", "comment"),
+                ("TCOM", "composer"),
+                ("TLAN", "language"),
+                ("TCOP", "copyright"),
+                // ID3v2.2 three-character frame IDs.
+                ("TT2", "title"),
+                ("TP1", "artist"),
+                ("TAL", "album"),
+                ("TP2", "album_artist"),
+                ("TRK", "track"),
+                ("TPA", "disc"),
+                ("TYE", "date"),
+                ("TCO", "genre"),
+                ("COM", "comment"),
+                ("TCM", "composer"),
+            ],
+            TagFamily::VorbisComment => &[
+                ("ALBUMARTIST", "album_artist"),
+                ("ALBUM ARTIST", "album_artist"),
+                ("TRACKNUMBER", "track"),
+                ("TRACKTOTAL", "track_total"),
+                ("TOTALTRACKS", "track_total"),
+                ("DISCNUMBER", "disc"),
+                ("DISCTOTAL", "disc_total"),
+                ("TOTALDISCS", "disc_total"),
+                ("YEAR", "date"),
+                ("DESCRIPTION", "comment"),
+            ],
+            TagFamily::Mp4 => &[
+                ("\u{a9}nam", "title"),
+                ("\u{a9}ART", "artist"),
+                ("\u{a9}alb", "album"),
+                ("aART", "album_artist"),
+                ("trkn", "track"),
+                ("disk", "disc"),
+                ("\u{a9}day", "date"),
+                ("\u{a9}gen", "genre"),
+                ("gnre", "genre"),
+                ("\u{a9}cmt", "comment"),
+                ("\u{a9}wrt", "composer"),
+                ("cprt", "copyright"),
+            ],
+            TagFamily::Matroska => &[
+                ("PART_NUMBER", "track"),
+                ("TOTAL_PARTS", "track_total"),
+                ("LEAD_PERFORMER", "artist"),
+                ("DATE_RELEASED", "date"),
+                ("DATE_RECORDED", "date"),
+            ],
+            TagFamily::Other => &[],
+        }
+    }
+}
+
+// Keys libavformat converts container-specific keys to.
+const GENERIC_KEYS: &[(&str, &str)] = &[
+    ("title", "title"),
+    ("artist", "artist"),
+    ("album", "album"),
+    ("album_artist", "album_artist"),
+    ("track", "track"),
+    ("disc", "disc"),
+    ("date", "date"),
+    ("genre", "genre"),
+    ("comment", "comment"),
+    ("composer", "composer"),
+    ("language", "language"),
+    ("copyright", "copyright"),
+];
+
+// A container-independent view of the common tags.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct NormalizedTags {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub track: Option<String>,
+    pub track_total: Option<String>,
+    pub disc: Option<String>,
+    pub disc_total: Option<String>,
+    pub date: Option<String>,
+    pub genre: Option<String>,
+    pub comment: Option<String>,
+    pub composer: Option<String>,
+    pub language: Option<String>,
+    pub copyright: Option<String>,
+}
+
+impl NormalizedTags {
+    // Normalizes tags from a number of sources in order of precedence;
+    // the first value found for each field wins.
+    pub fn new(sources: &[&Tags], family: TagFamily) -> NormalizedTags {
+        let mut normalized = NormalizedTags::default();
+        for tags in sources {
+            for (key, value) in tags.iter() {
+                let field = GENERIC_KEYS
+                    .iter()
+                    .chain(family.keys())
+                    .find(|(k, _)| k.eq_ignore_ascii_case(key))
+                    .map(|&(_, field)| field);
+                if let Some(field) = field {
+                    normalized.set(field, value.trim());
+                }
+            }
+        }
+        normalized
+    }
+
+    // The value of a field by name, e.g., "album_artist".
+    pub fn get(&self, field: &str) -> Option<&str> {
+        let value = match field.to_ascii_lowercase().as_str() {
+            "title" => &self.title,
+            "artist" => &self.artist,
+            "album" => &self.album,
+            "album_artist" => &self.album_artist,
+            "track" => &self.track,
+            "track_total" => &self.track_total,
+            "disc" => &self.disc,
+            "disc_total" => &self.disc_total,
+            "date" => &self.date,
+            "genre" => &self.genre,
+            "comment" => &self.comment,
+            "composer" => &self.composer,
+            "language" => &self.language,
+            "copyright" => &self.copyright,
+            _ => return None,
+        };
+        value.as_deref()
+    }
+
+    fn field_mut(&mut self, field: &str) -> &mut Option<String> {
+        match field {
+            "title" => &mut self.title,
+            "artist" => &mut self.artist,
+            "album" => &mut self.album,
+            "album_artist" => &mut self.album_artist,
+            "track" => &mut self.track,
+            "track_total" => &mut self.track_total,
+            "disc" => &mut self.disc,
+            "disc_total" => &mut self.disc_total,
+            "date" => &mut self.date,
+            "genre" => &mut self.genre,
+            "comment" => &mut self.comment,
+            "composer" => &mut self.composer,
+            "language" => &mut self.language,
+            "copyright" => &mut self.copyright,
+            _ => unreachable!("unknown normalized tag {}", field),
+        }
+    }
+
+    fn set(&mut self, field: &str, value: &str) {
+        if value.is_empty() {
+            return;
+        }
+        // Track and disc numbers may come as "n/total".
+        let (value, total) = match (field, value.split_once('/')) {
+            ("track", Some((n, total))) | ("disc", Some((n, total))) => {
+                (n.trim(), Some(total.trim()))
+            }
+            _ => (value, None),
+        };
+        let slot = self.field_mut(field);
+        if slot.is_none() && !value.is_empty() {
+            *slot = Some(value.to_string());
+        }
+        if let Some(total) = total.filter(|t| !t.is_empty()) {
+            let total_slot = self.field_mut(&format!("{}_total", field));
+            if total_slot.is_none() {
+                *total_slot = Some(total.to_string());
+            }
+        }
+    }
+}
+
+// Whether a tag is boring by the built-in rules: some fixed names, plus
+// tags beginning with an underscore (e.g., _STATISTICS_* tags by
+// mkvmerge), or in reversed domain name notation (e.g.,
+// com.apple.quicktime.player.* tags). Lyrics are hidden as well, as they
+// are usually far too long and can be extracted separately.
+pub fn tag_is_boring_by_default(key: &str) -> bool {
+    lazy_static! {
+        static ref BORING_PATTERN: Regex = Regex::new(r"(?i)^((major_brand|minor_version|compatible_brands|creation_time|handler_name|encoder)$|_|com\.)").unwrap();
+    }
+    BORING_PATTERN.is_match(key) || lyrics::is_lyrics_key(key)
+}
+
+pub fn filter_tags<F: Fn(&str) -> bool>(tags: &Tags, is_boring: F) -> Tags {
+    tags.iter()
+        .filter(|(k, _)| !is_boring(k))
+        .cloned()
+        .collect()
+}
+
 pub trait ToTags {
     fn to_tags(&self) -> Tags;
 
     fn to_filtered_tags(&self) -> Tags {
-        self.to_tags()
-            .iter()
-            .filter(|(k, _)| !Self::tag_is_boring(&k))
-            .cloned()
-            .collect()
+        filter_tags(&self.to_tags(), Self::tag_is_boring)
     }
 
     fn tag_is_boring(key: &str) -> bool {
-        lazy_static! {
-            // Some fixed names, plus tags beginning with an underscore (e.g.,
-            // _STATISTICS_* tags by mkvmerge), or in reversed domain name notation
-            // (e.g., com.apple.quicktime.player.* tags).
-            static ref BORING_PATTERN: Regex = Regex::new(r"(?i)^((major_brand|minor_version|compatible_brands|creation_time|handler_name|encoder)$|_|com\.)").unwrap();
+        tag_is_boring_by_default(key)
+    }
+}
+
+// User rules for hiding or showing tags on top of the built-in ones. Each
+// rule is a case-insensitive regex matched against entire keys; the last
+// matching rule wins, and keys matching no rule fall back to the built-in
+// rules.
+#[derive(Clone, Debug, Default)]
+pub struct TagRules {
+    rules: Vec<(Regex, bool)>,
+}
+
+impl TagRules {
+    pub fn new() -> TagRules {
+        TagRules::default()
+    }
+
+    fn add(&mut self, pattern: &str, boring: bool) -> io::Result<&mut TagRules> {
+        let regex = Regex::new(&format!("(?i)^(?:{})$", pattern)).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid tag pattern \"{}\": {}", pattern, e),
+            )
+        })?;
+        self.rules.push((regex, boring));
+        Ok(self)
+    }
+
+    pub fn hide(&mut self, pattern: &str) -> io::Result<&mut TagRules> {
+        self.add(pattern, true)
+    }
+
+    pub fn show(&mut self, pattern: &str) -> io::Result<&mut TagRules> {
+        self.add(pattern, false)
+    }
+
+    // Adds rules from a config file, one per line, e.g.,
+    //
+    //     # mkvmerge statistics are never interesting
+    //     hide _STATISTICS_.*
+    //     show encoder
+    pub fn load(&mut self, path: &Path) -> io::Result<&mut TagRules> {
+        let text = fs::read_to_string(path)?;
+        self.parse(&text)
+    }
+
+    fn parse(&mut self, text: &str) -> io::Result<&mut TagRules> {
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.split_once(char::is_whitespace) {
+                Some(("hide", pattern)) => self.hide(pattern.trim())?,
+                Some(("show", pattern)) => self.show(pattern.trim())?,
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("invalid tag rule on line {}: {}", i + 1, line),
+                    ))
+                }
+            };
         }
-        BORING_PATTERN.is_match(key)
+        Ok(self)
+    }
+
+    pub fn is_boring(&self, key: &str) -> bool {
+        self.rules
+            .iter()
+            .rev()
+            .find(|(regex, _)| regex.is_match(key))
+            .map_or_else(|| tag_is_boring_by_default(key), |&(_, boring)| boring)
     }
 }
 
@@ -42,3 +340,70 @@ impl<'a> ToTags for DictionaryRef<'a> {
             .collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(pairs: &[(&str, &str)]) -> Tags {
+        pairs
+            .iter()
+            .map(|&(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn tag_rules() {
+        let mut rules = TagRules::new();
+        rules
+            .parse("# comment\n\nshow _.*\nhide _statistics_.*\nhide title\n")
+            .unwrap();
+        rules.show("ENCODER").unwrap();
+        assert!(rules.is_boring("_STATISTICS_TAGS"));
+        assert!(!rules.is_boring("_OTHER"));
+        assert!(rules.is_boring("title"));
+        assert!(!rules.is_boring("encoder"));
+        assert!(rules.is_boring("major_brand"));
+        assert!(!rules.is_boring("artist"));
+        assert!(rules.is_boring("lyrics-eng"));
+        assert!(rules.parse("hide").is_err());
+        assert!(rules.hide("(").is_err());
+    }
+
+    #[test]
+    fn normalize_vorbis_comments() {
+        let file_tags = tags(&[
+            ("ARTIST", "Artist"),
+            ("ALBUMARTIST", "Various Artists"),
+            ("TRACKNUMBER", "3"),
+            ("TRACKTOTAL", "12"),
+            ("DISCNUMBER", "1/2"),
+        ]);
+        let stream_tags = tags(&[("ARTIST", "Someone else"), ("TITLE", "Title")]);
+        let normalized = NormalizedTags::new(&[&file_tags, &stream_tags], TagFamily::VorbisComment);
+        assert_eq!(Some("Artist"), normalized.artist.as_deref());
+        assert_eq!(Some("Title"), normalized.title.as_deref());
+        assert_eq!(Some("Various Artists"), normalized.album_artist.as_deref());
+        assert_eq!(Some("3"), normalized.track.as_deref());
+        assert_eq!(Some("12"), normalized.track_total.as_deref());
+        assert_eq!(Some("1"), normalized.disc.as_deref());
+        assert_eq!(Some("2"), normalized.disc_total.as_deref());
+    }
+
+    #[test]
+    fn normalize_mp4_atoms() {
+        let file_tags = tags(&[
+            ("\u{a9}ART", "Artist"),
+            ("trkn", "5/10"),
+            ("major_brand", "M4A"),
+        ]);
+        let normalized = NormalizedTags::new(&[&file_tags], TagFamily::Mp4);
+        assert_eq!(Some("Artist"), normalized.artist.as_deref());
+        assert_eq!(Some("5"), normalized.track.as_deref());
+        assert_eq!(Some("10"), normalized.track_total.as_deref());
+        assert_eq!(None, normalized.title);
+        // Not part of the MP4 conventions.
+        let normalized = NormalizedTags::new(&[&file_tags], TagFamily::Other);
+        assert_eq!(None, normalized.artist);
+    }
+}
@@ -1,7 +1,11 @@
+use md5::Md5;
+use sha1::Sha1;
 use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
 use std::fs;
-use std::io;
+use std::io::{self, Read};
 use std::path::Path;
+use std::str::FromStr;
 
 #[derive(Clone, Copy, Debug)]
 pub enum Base {
@@ -51,12 +55,216 @@ pub fn format_seconds(secs: f64) -> String {
     format!("{:02.0}:{:02.0}:{:05.2}", hours, minutes, seconds)
 }
 
-pub fn sha256_hash(path: &Path) -> io::Result<String> {
+// Formats a codec tag as a fourcc string, the same way as
+// av_fourcc_make_string: unprintable bytes are shown as [n].
+pub fn fourcc(tag: u32) -> String {
+    tag.to_le_bytes()
+        .iter()
+        .map(|&b| {
+            if b.is_ascii_alphanumeric() || b == b'.' || b == b'_' || b == b' ' {
+                (b as char).to_string()
+            } else {
+                format!("[{}]", b)
+            }
+        })
+        .collect()
+}
+
+// Tags are printed after a 4-space indent, with keys (and ": ") padded to
+// 20 characters.
+const TAG_INDENT: usize = 4;
+const TAG_KEY_WIDTH: usize = 20;
+
+// Column at which the value of a tag with the given key starts; keys too
+// long for the padding push it to the right.
+pub fn tag_value_column(key: &str) -> usize {
+    TAG_INDENT + TAG_KEY_WIDTH.max(key.chars().count() + 2)
+}
+
+// Values are wrapped to at least this many columns, however narrow the
+// terminal.
+const MIN_WRAP_WIDTH: usize = 20;
+
+fn wrap_line(line: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    let mut current_len = 0;
+    for word in line.split_whitespace() {
+        let mut word = word.chars().collect::<Vec<_>>();
+        if current_len > 0 && current_len + 1 + word.len() > width {
+            lines.push(current);
+            current = String::new();
+            current_len = 0;
+        }
+        // Words longer than a whole line are broken up.
+        while word.len() > width {
+            if current_len > 0 {
+                lines.push(current);
+                current = String::new();
+                current_len = 0;
+            }
+            lines.push(word.drain(..width).collect());
+        }
+        if word.is_empty() {
+            continue;
+        }
+        if current_len > 0 {
+            current.push(' ');
+            current_len += 1;
+        }
+        current.extend(word.iter());
+        current_len += word.len();
+    }
+    if current_len > 0 || lines.is_empty() {
+        lines.push(current);
+    }
+    lines
+}
+
+// Formats a tag value for display after a padded key, starting at the
+// given column: values longer than max_length characters are truncated
+// with an ellipsis, lines are wrapped to fit within width columns if given,
+// and continuation lines (including those of values with embedded
+// newlines) are indented to line up with the first.
+pub fn format_tag_value(
+    value: &str,
+    column: usize,
+    width: Option<usize>,
+    max_length: Option<usize>,
+) -> String {
+    let value = match max_length {
+        Some(max) if value.chars().count() > max => {
+            value
+                .chars()
+                .take(max)
+                .collect::<String>()
+                .trim_end()
+                .to_string()
+                + "\u{2026}"
+        }
+        _ => value.to_string(),
+    };
+    let lines = value.lines().map(|line| line.trim_end());
+    let lines: Vec<String> = match width {
+        Some(width) => {
+            let width = width.saturating_sub(column).max(MIN_WRAP_WIDTH);
+            lines.flat_map(|line| wrap_line(line, width)).collect()
+        }
+        None => lines.map(str::to_string).collect(),
+    };
+    lines.join(&format!("\n{}", " ".repeat(column)))
+}
+
+// Declaration order is display order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChecksumAlgorithm {
+    Md5,
+    Sha1,
+    Sha256,
+    Blake3,
+}
+
+impl ChecksumAlgorithm {
+    pub fn name(self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Md5 => "MD5",
+            ChecksumAlgorithm::Sha1 => "SHA-1",
+            ChecksumAlgorithm::Sha256 => "SHA-256",
+            ChecksumAlgorithm::Blake3 => "BLAKE3",
+        }
+    }
+}
+
+impl FromStr for ChecksumAlgorithm {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> io::Result<ChecksumAlgorithm> {
+        match s.to_ascii_lowercase().replace('-', "").as_str() {
+            "md5" => Ok(ChecksumAlgorithm::Md5),
+            "sha1" => Ok(ChecksumAlgorithm::Sha1),
+            "sha256" => Ok(ChecksumAlgorithm::Sha256),
+            "blake3" | "b3" => Ok(ChecksumAlgorithm::Blake3),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown checksum algorithm \"{}\"", s),
+            )),
+        }
+    }
+}
+
+// Incremental hashing with any of the algorithms.
+pub enum Hasher {
+    Md5(Md5),
+    Sha1(Sha1),
+    Sha256(Sha256),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    pub fn new(algorithm: ChecksumAlgorithm) -> Hasher {
+        match algorithm {
+            ChecksumAlgorithm::Md5 => Hasher::Md5(Md5::new()),
+            ChecksumAlgorithm::Sha1 => Hasher::Sha1(Sha1::new()),
+            ChecksumAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            ChecksumAlgorithm::Blake3 => Hasher::Blake3(Box::new(blake3::Hasher::new())),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Md5(h) => h.update(data),
+            Hasher::Sha1(h) => h.update(data),
+            Hasher::Sha256(h) => h.update(data),
+            Hasher::Blake3(h) => {
+                h.update(data);
+            }
+        }
+    }
+
+    // Lowercase hex.
+    pub fn finalize(self) -> String {
+        match self {
+            Hasher::Md5(h) => format!("{:x}", h.finalize()),
+            Hasher::Sha1(h) => format!("{:x}", h.finalize()),
+            Hasher::Sha256(h) => format!("{:x}", h.finalize()),
+            Hasher::Blake3(h) => h.finalize().to_hex().to_string(),
+        }
+    }
+}
+
+// Computes hex digests of the file with each of the algorithms, reading
+// the file only once.
+pub fn checksums(
+    path: &Path,
+    algorithms: &[ChecksumAlgorithm],
+) -> io::Result<BTreeMap<ChecksumAlgorithm, String>> {
     let mut file = fs::File::open(path)?;
-    let mut hasher = Sha256::new();
-    io::copy(&mut file, &mut hasher)?;
-    let hash = hasher.finalize();
-    Ok(format!("{:x}", hash))
+    let mut hashers = algorithms
+        .iter()
+        .map(|&a| (a, Hasher::new(a)))
+        .collect::<BTreeMap<_, _>>();
+    let mut buffer = vec![0u8; 1 << 20];
+    loop {
+        let n = match file.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        for hasher in hashers.values_mut() {
+            hasher.update(&buffer[..n]);
+        }
+    }
+    Ok(hashers
+        .into_iter()
+        .map(|(a, hasher)| (a, hasher.finalize()))
+        .collect())
+}
+
+pub fn sha256_hash(path: &Path) -> io::Result<String> {
+    let mut checksums = checksums(path, &[ChecksumAlgorithm::Sha256])?;
+    Ok(checksums.remove(&ChecksumAlgorithm::Sha256).unwrap())
 }
 
 #[cfg(test)]
@@ -171,6 +379,50 @@ mod tests {
         }
     }
 
+    #[test]
+    fn fourcc_formats_codec_tags() {
+        assert_eq!("hvc1", fourcc(0x3163_7668));
+        assert_eq!("mp4a", fourcc(0x6134_706d));
+        assert_eq!("[1][0][0][0]", fourcc(1));
+    }
+
+    #[test]
+    fn format_tag_value_wraps_and_truncates() {
+        let column = tag_value_column("title");
+        let indent = " ".repeat(24);
+        assert_eq!(24, column);
+        assert_eq!("short", format_tag_value("short", column, Some(80), None));
+        assert_eq!(
+            format!("first line\n{}second line", indent),
+            format_tag_value("first line\r\nsecond line", column, None, None)
+        );
+        assert_eq!(
+            format!("lorem ipsum dolor sit\n{}amet, consectetur", indent),
+            format_tag_value(
+                "lorem ipsum dolor sit amet, consectetur",
+                column,
+                Some(45),
+                None
+            )
+        );
+        assert_eq!(
+            format!("aaaaaaaaaaaaaaaaaaaa\n{}aaaaa", indent),
+            format_tag_value(&"a".repeat(25), column, Some(30), None)
+        );
+        assert_eq!(
+            "lorem ipsum\u{2026}",
+            format_tag_value("lorem ipsum dolor", column, None, Some(12))
+        );
+
+        // Continuation lines line up with values after long keys, too.
+        let column = tag_value_column("MusicBrainz Album Artist Id");
+        assert_eq!(33, column);
+        assert_eq!(
+            format!("first line\n{}second line", " ".repeat(33)),
+            format_tag_value("first line\nsecond line", column, None, None)
+        );
+    }
+
     #[test]
     fn sha256_hash_returns_correct_hash() {
         let file = NamedTempFile::new().unwrap();
@@ -181,4 +433,26 @@ mod tests {
             sha256_hash(file.path()).unwrap()
         );
     }
+
+    #[test]
+    fn checksums_in_one_pass() {
+        let file = NamedTempFile::new().unwrap();
+        file.as_file().write_all(b"abc").unwrap();
+        let algorithms = ["blake3", "MD5", "sha-1", "sha256"]
+            .iter()
+            .map(|a| a.parse::<ChecksumAlgorithm>().unwrap())
+            .collect::<Vec<_>>();
+        let checksums = checksums(file.path(), &algorithms).unwrap();
+        assert_eq!(
+            vec![
+                // printf abc | md5sum, sha1sum, sha256sum, b3sum
+                "900150983cd24fb0d6963f7d28e17f72",
+                "a9993e364706816aba3e25717850c26c9cd0d89d",
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+                "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85",
+            ],
+            checksums.values().collect::<Vec<_>>()
+        );
+        assert!("crc32".parse::<ChecksumAlgorithm>().is_err());
+    }
 }
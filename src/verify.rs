@@ -0,0 +1,297 @@
+// Integrity checking by reading every packet and decoding every audio and
+// video stream end to end.
+
+use crate::ffmpeg;
+use crate::ffmpeg::decoder;
+use crate::ffmpeg::format::context::Input;
+use crate::ffmpeg::media::Type;
+use crate::ffmpeg::{Frame, Packet};
+use std::collections::HashMap;
+use std::io;
+
+use crate::decode;
+use crate::util;
+
+// Give up reading after this many consecutive read errors.
+const MAX_CONSECUTIVE_READ_ERRORS: u32 = 64;
+
+// A file whose packets end more than this many seconds before its
+// advertised duration is considered truncated.
+const TRUNCATION_TOLERANCE: f64 = 1f64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueKind {
+    ReadError,
+    CorruptPacket,
+    DecodeError,
+    TimestampError,
+    Truncated,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct IntegrityIssue {
+    // None for issues with the file as a whole.
+    pub index: Option<usize>,
+    pub kind: IssueKind,
+    pub count: u64,
+    // Where the issue is, e.g., "Stream #0" or "File".
+    pub location: String,
+    // Time of the first occurrence since the start of the file, if known.
+    #[serde(skip_serializing)]
+    pub _first: Option<f64>,
+    pub first: Option<String>,
+    pub description: String,
+}
+
+impl IntegrityIssue {
+    fn new(index: Option<usize>, kind: IssueKind, count: u64, first: Option<f64>) -> Self {
+        let noun = match kind {
+            IssueKind::ReadError => "read error",
+            IssueKind::CorruptPacket => "corrupt packet",
+            IssueKind::DecodeError => "decode error",
+            IssueKind::TimestampError => "timestamp error",
+            IssueKind::Truncated => "truncation",
+        };
+        IntegrityIssue {
+            index,
+            location: match index {
+                Some(i) => format!("Stream #{}", i),
+                None => "File".to_string(),
+            },
+            kind,
+            count,
+            _first: first,
+            first: first.map(util::format_seconds),
+            description: format!("{} {}{}", count, noun, if count == 1 { "" } else { "s" }),
+        }
+    }
+}
+
+// Occurrences of one kind of issue in one stream (or the whole file).
+#[derive(Default)]
+struct Tally {
+    count: u64,
+    first: Option<f64>,
+}
+
+impl Tally {
+    fn add(&mut self, time: Option<f64>) {
+        self.count += 1;
+        if self.first.is_none() {
+            self.first = time;
+        }
+    }
+}
+
+struct StreamState {
+    time_base: f64,
+    // Start of the file in seconds, subtracted from all packet times.
+    start: f64,
+    decoder: Option<decoder::Opened>,
+    last_dts: Option<i64>,
+    // Time of the most recent packet.
+    last_time: Option<f64>,
+    end_time: Option<f64>,
+    tallies: HashMap<IssueKind, Tally>,
+}
+
+impl StreamState {
+    fn tally(&mut self, kind: IssueKind, time: Option<f64>) {
+        self.tallies.entry(kind).or_default().add(time);
+    }
+
+    fn receive_frames(&mut self, frame: &mut Frame) {
+        if let Some(decoder) = self.decoder.as_mut() {
+            let mut errors = 0;
+            loop {
+                match decoder.receive_frame(frame) {
+                    Ok(()) if frame.is_corrupt() => errors += 1,
+                    Ok(()) => (),
+                    Err(ffmpeg::Error::Eof) => break,
+                    Err(ffmpeg::Error::Other { errno }) if errno == ffmpeg::error::EAGAIN => break,
+                    Err(_) => {
+                        errors += 1;
+                        break;
+                    }
+                }
+            }
+            let time = self.last_time;
+            for _ in 0..errors {
+                self.tally(IssueKind::DecodeError, time);
+            }
+        }
+    }
+}
+
+// Reads all packets of the input, decoding audio and video streams, and
+// returns the issues found, ordered by stream. An empty result means the
+// file appears to be intact.
+pub fn verify_integrity(input: &mut Input) -> io::Result<Vec<IntegrityIssue>> {
+    let start = match unsafe { (*input.as_ptr()).start_time } {
+        ffmpeg::ffi::AV_NOPTS_VALUE => 0f64,
+        t => t as f64 / ffmpeg::ffi::AV_TIME_BASE as f64,
+    };
+    let mut states: HashMap<usize, StreamState> = HashMap::new();
+    for stream in input.streams() {
+        let medium = stream.parameters().medium();
+        // Streams without a usable decoder (not the file's fault) are only
+        // checked at the packet level.
+        let decoder = if medium == Type::Video || medium == Type::Audio {
+            decode::open_decoder(&stream)
+        } else {
+            None
+        };
+        states.insert(
+            stream.index(),
+            StreamState {
+                time_base: f64::from(stream.time_base()),
+                start,
+                decoder,
+                last_dts: None,
+                last_time: None,
+                end_time: None,
+                tallies: HashMap::new(),
+            },
+        );
+    }
+
+    let mut read_errors = Tally::default();
+    let mut last_time: Option<f64> = None;
+    let mut consecutive_read_errors = 0;
+    let mut frame = unsafe { Frame::empty() };
+    let mut packet = Packet::empty();
+    loop {
+        match packet.read(input) {
+            Ok(()) => consecutive_read_errors = 0,
+            Err(ffmpeg::Error::Eof) => break,
+            Err(_) => {
+                read_errors.add(last_time);
+                consecutive_read_errors += 1;
+                if consecutive_read_errors >= MAX_CONSECUTIVE_READ_ERRORS {
+                    break;
+                }
+                continue;
+            }
+        }
+
+        let state = match states.get_mut(&packet.stream()) {
+            Some(state) => state,
+            None => continue,
+        };
+        let time = packet
+            .pts()
+            .or_else(|| packet.dts())
+            .map(|ts| ts as f64 * state.time_base - state.start);
+        if time.is_some() {
+            state.last_time = time;
+            last_time = time;
+        }
+        if let Some(t) = time {
+            let end = t + packet.duration().max(0) as f64 * state.time_base;
+            state.end_time = Some(state.end_time.map_or(end, |e| e.max(end)));
+        }
+
+        if packet.is_corrupt() {
+            state.tally(IssueKind::CorruptPacket, time);
+        }
+        // Repeated DTS are normal, e.g., for simultaneous subtitle events,
+        // and streams that are not decoded are not checked.
+        if let Some(dts) = packet.dts() {
+            if state.decoder.is_some() && state.last_dts.is_some_and(|last| dts < last) {
+                state.tally(IssueKind::TimestampError, time);
+            }
+            state.last_dts = Some(dts);
+        }
+
+        let sent = match state.decoder.as_mut() {
+            Some(decoder) => decoder.send_packet(&packet).is_ok(),
+            None => continue,
+        };
+        if !sent {
+            state.tally(IssueKind::DecodeError, time);
+        }
+        state.receive_frames(&mut frame);
+    }
+
+    for state in states.values_mut() {
+        let flushed = match state.decoder.as_mut() {
+            Some(decoder) => decoder.send_eof().is_ok(),
+            None => false,
+        };
+        if flushed {
+            state.receive_frames(&mut frame);
+        }
+    }
+
+    let mut issues = Vec::new();
+    if read_errors.count > 0 {
+        issues.push(IntegrityIssue::new(
+            None,
+            IssueKind::ReadError,
+            read_errors.count,
+            read_errors.first,
+        ));
+    }
+    if let Some(truncation) = check_truncation(input, &states) {
+        issues.push(truncation);
+    }
+    let mut indices = states.keys().cloned().collect::<Vec<_>>();
+    indices.sort();
+    for index in indices {
+        for kind in [
+            IssueKind::CorruptPacket,
+            IssueKind::DecodeError,
+            IssueKind::TimestampError,
+        ] {
+            if let Some(tally) = states[&index].tallies.get(&kind) {
+                issues.push(IntegrityIssue::new(
+                    Some(index),
+                    kind,
+                    tally.count,
+                    tally.first,
+                ));
+            }
+        }
+    }
+    Ok(issues)
+}
+
+// Compares where the packets actually end against the advertised duration
+// of the file.
+fn check_truncation(input: &Input, states: &HashMap<usize, StreamState>) -> Option<IntegrityIssue> {
+    if input.duration() <= 0 {
+        return None;
+    }
+    let expected_end = input.duration() as f64 / ffmpeg::ffi::AV_TIME_BASE as f64;
+    let actual_end = states
+        .values()
+        .filter_map(|s| s.end_time)
+        .fold(0f64, f64::max);
+    if expected_end - actual_end > TRUNCATION_TOLERANCE {
+        let mut issue = IntegrityIssue::new(None, IssueKind::Truncated, 1, None);
+        issue.description = format!(
+            "truncated, ends at {} of {}",
+            util::format_seconds(actual_end),
+            util::format_seconds(expected_end),
+        );
+        Some(issue)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn issue_descriptions() {
+        let issue = IntegrityIssue::new(Some(1), IssueKind::DecodeError, 3, Some(62.5));
+        assert_eq!("Stream #1", issue.location);
+        assert_eq!("3 decode errors", issue.description);
+        let issue = IntegrityIssue::new(None, IssueKind::ReadError, 1, None);
+        assert_eq!("File", issue.location);
+        assert_eq!("1 read error", issue.description);
+    }
+}
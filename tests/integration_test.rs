@@ -3,7 +3,9 @@ extern crate metadata;
 extern crate ffmpeg_next as ffmpeg;
 extern crate tempfile;
 
-use metadata::{MediaFileMetadata, Render};
+use metadata::edit::write_tags;
+use metadata::streamhash::hash_streams;
+use metadata::{MediaFileMetadata, Render, StreamMetadata, TagEdits, Tags};
 use std::fs::File;
 use std::io::Write;
 use std::path::Path;
@@ -314,3 +316,206 @@ media_file_tests! {
         output_with_all_tags: "data/vp9_webm/vp9.webm.with_all_tags.txt",
     }
 }
+
+#[test]
+fn integrity_check_detects_truncation() {
+    ffmpeg::init().unwrap();
+    unsafe {
+        ffmpeg::ffi::av_log_set_level(ffmpeg::ffi::AV_LOG_FATAL);
+    }
+
+    let input = include_bytes!("data/vp8_webm/vp8.webm");
+    let tmpdir = TempDir::new().unwrap();
+    let intact_path = tmpdir.path().join("intact.webm");
+    File::create(&intact_path)
+        .unwrap()
+        .write_all(input)
+        .unwrap();
+    // Cut into the first cluster, keeping the headers (including the
+    // advertised duration) intact.
+    let truncated_path = tmpdir.path().join("truncated.webm");
+    File::create(&truncated_path)
+        .unwrap()
+        .write_all(&input[..600])
+        .unwrap();
+
+    let mut meta = MediaFileMetadata::new(&intact_path).unwrap();
+    meta.include_integrity_check(true).unwrap();
+    assert!(!meta.is_damaged());
+
+    let mut meta = MediaFileMetadata::new(&truncated_path).unwrap();
+    meta.include_integrity_check(true).unwrap();
+    assert!(meta.is_damaged());
+
+    // Subtitle streams do not count towards timestamp errors.
+    let mut meta = MediaFileMetadata::new(
+        &Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/h264_aac_srt_mkv/h264.aac.srt.mkv"),
+    )
+    .unwrap();
+    meta.include_integrity_check(true).unwrap();
+    assert!(!meta.is_damaged());
+}
+
+#[test]
+fn caption_detection_without_captions() {
+    ffmpeg::init().unwrap();
+    unsafe {
+        ffmpeg::ffi::av_log_set_level(ffmpeg::ffi::AV_LOG_FATAL);
+    }
+
+    let tmpdir = TempDir::new().unwrap();
+    for (filename, input) in [
+        ("h264.mp4", &include_bytes!("data/h264_mp4/h264.mp4")[..]),
+        // Cover art, which is not checked.
+        (
+            "mp3.jpeg.mp3",
+            &include_bytes!("data/mp3_jpeg_mp3/mp3.jpeg.mp3")[..],
+        ),
+    ] {
+        let input_path = tmpdir.path().join(filename);
+        File::create(&input_path).unwrap().write_all(input).unwrap();
+
+        let mut meta = MediaFileMetadata::new(&input_path).unwrap();
+        meta.include_caption_detection(true).unwrap();
+        assert!(!meta.render_default().unwrap().contains("with CC"));
+        for stream in &meta._streams_metadata {
+            if let StreamMetadata::VideoMetadata(video) = stream {
+                let expected = if filename.ends_with(".mp4") {
+                    Some(false)
+                } else {
+                    None
+                };
+                assert_eq!(expected, video.closed_captions);
+            }
+        }
+    }
+}
+
+#[test]
+fn codec_profiles_reported_by_libavcodec() {
+    ffmpeg::init().unwrap();
+    unsafe {
+        ffmpeg::ffi::av_log_set_level(ffmpeg::ffi::AV_LOG_FATAL);
+    }
+
+    let tmpdir = TempDir::new().unwrap();
+    for (filename, input, expected) in [
+        // 8-bit 4:2:0, encoded with rav1e.
+        (
+            "av1.ivf",
+            &include_bytes!("data/av1_ivf/av1.ivf")[..],
+            "AV1 (Main Profile",
+        ),
+        // 5.1 in Ogg, encoded with libopus.
+        (
+            "5.1.opus",
+            &include_bytes!("data/_5_1_opus/5.1.opus")[..],
+            "Opus (mapping family 1: Vorbis channel order)",
+        ),
+    ] {
+        let input_path = tmpdir.path().join(filename);
+        File::create(&input_path).unwrap().write_all(input).unwrap();
+
+        let meta = MediaFileMetadata::new(&input_path).unwrap();
+        let codec_desc = match &meta._streams_metadata[0] {
+            StreamMetadata::VideoMetadata(video) => &video.codec_desc,
+            StreamMetadata::AudioMetadata(audio) => &audio.codec_desc,
+            _ => panic!("{}: stream #0 is neither video nor audio", filename),
+        };
+        assert!(
+            codec_desc.starts_with(expected),
+            "{}: {}",
+            filename,
+            codec_desc
+        );
+    }
+}
+
+#[test]
+fn write_tags_end_to_end() {
+    ffmpeg::init().unwrap();
+    unsafe {
+        ffmpeg::ffi::av_log_set_level(ffmpeg::ffi::AV_LOG_FATAL);
+    }
+
+    let tmpdir = TempDir::new().unwrap();
+    let input_path = tmpdir.path().join("h264.mp4");
+    File::create(&input_path)
+        .unwrap()
+        .write_all(include_bytes!("data/h264_mp4/h264.mp4"))
+        .unwrap();
+    let output_path = tmpdir.path().join("edited.mp4");
+
+    let mut edits = TagEdits::new();
+    edits
+        .set_tag(None, "title", "Edited title")
+        .remove_tag(None, "encoder")
+        .set_language(0, "jpn");
+    edits.set_disposition(0, "default", true).unwrap();
+    let meta = write_tags(&input_path, &output_path, &edits).unwrap();
+
+    let tag = |tags: &Tags, key: &str| {
+        tags.iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.to_string())
+    };
+    assert_eq!(Some("Edited title".to_string()), tag(&meta.tags, "title"));
+    assert_eq!(
+        Some("jpn".to_string()),
+        tag(&meta.streams_tags[0].tags, "language")
+    );
+    let output = ffmpeg::format::input(&output_path).unwrap();
+    let stream = output.stream(0).unwrap();
+    assert!(stream
+        .disposition()
+        .contains(ffmpeg::format::stream::Disposition::DEFAULT));
+
+    // Edits that do not survive leave no output behind; the MP4 muxer
+    // drops unknown container tags.
+    let mut edits = TagEdits::new();
+    edits.set_tag(None, "frobnicate", "yes");
+    assert!(write_tags(&input_path, &tmpdir.path().join("bad.mp4"), &edits).is_err());
+    assert!(!tmpdir.path().join("bad.mp4").exists());
+}
+
+#[test]
+fn stream_hashes_survive_remuxing() {
+    ffmpeg::init().unwrap();
+    unsafe {
+        ffmpeg::ffi::av_log_set_level(ffmpeg::ffi::AV_LOG_FATAL);
+    }
+
+    let tmpdir = TempDir::new().unwrap();
+    let input_path = tmpdir.path().join("h264.aac.mp4");
+    File::create(&input_path)
+        .unwrap()
+        .write_all(include_bytes!("data/h264_aac_mp4/h264.aac.mp4"))
+        .unwrap();
+    let output_path = tmpdir.path().join("remuxed.mkv");
+    write_tags(&input_path, &output_path, &TagEdits::new()).unwrap();
+
+    let hash = |path: &Path, decode_frames: bool| {
+        let mut input = ffmpeg::format::input(&path).unwrap();
+        hash_streams(&mut input, decode_frames).unwrap()
+    };
+    let original = hash(&input_path, true);
+    let remuxed = hash(&output_path, true);
+    assert_eq!(2, original.len());
+    assert_eq!(2, remuxed.len());
+    // Packets are hashed the same whether or not frames are decoded in the
+    // same pass.
+    for (a, b) in original.iter().zip(hash(&input_path, false).iter()) {
+        assert_eq!(a.packets, b.packets);
+        assert_eq!(a.packet_count, b.packet_count);
+        assert_eq!(None, b.frames);
+    }
+    // Packet payloads survive remuxing MP4 to Matroska for both streams;
+    // audio frames may not, as the containers signal encoder delay
+    // differently.
+    for (a, b) in original.iter().zip(remuxed.iter()) {
+        assert_eq!(a.packets, b.packets);
+        assert_eq!(a.packet_count, b.packet_count);
+        assert!(a.frames.is_some());
+    }
+    assert!(original[0].same_content(&remuxed[0]));
+}